@@ -0,0 +1,426 @@
+use std::{collections::HashMap, rc::Rc, sync::Arc, time::{Duration, Instant}};
+
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::{Window, WindowBuilder, WindowId}, dpi::PhysicalSize,
+};
+
+use crate::{core_2d::{drawable_2d::{Drawable2D, SimpleDrawable2D}, pipeline_2d::Pipeline2D}, renderer::{WgpuState, DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT, config::RendererConfig, error::RendererError, frame::Frame, gpu_context::GpuContext}};
+
+// After a long stall the simulation drops the time it's behind by instead of running this many ticks in a row.
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+// How the runner paces simulation ticks and frames.
+#[derive(Clone, Debug)]
+pub struct LoopConfig {
+    tick_rate: f64, // Updates per second
+    max_fps: Option<f64>,
+    wait_until_dirty: bool,
+}
+
+impl LoopConfig {
+    pub fn new() -> LoopConfig {
+        return LoopConfig {
+            tick_rate: 60.0,
+            max_fps: None,
+            wait_until_dirty: false,
+        }
+    }
+
+    pub fn with_tick_rate(mut self, hz: f64) -> LoopConfig {
+        self.tick_rate = hz.max(1.0);
+        return self;
+    }
+
+    // None draws as often as the present mode allows.
+    pub fn with_max_fps(mut self, max_fps: Option<f64>) -> LoopConfig {
+        self.max_fps = max_fps.map(|fps| fps.max(1.0));
+        return self;
+    }
+
+    // Only draws when something in the drawable tree changed or a redraw was requested. With nothing to draw the loop
+    // sleeps until the next event and doesn't tick. Playing sprites keep their tree dirty, for other animations
+    // AppContext::set_continuous_updates keeps it ticking.
+    pub fn with_wait_until_dirty(mut self, wait_until_dirty: bool) -> LoopConfig {
+        self.wait_until_dirty = wait_until_dirty;
+        return self;
+    }
+
+    pub fn get_tick_duration(&self) -> Duration {
+        return Duration::from_secs_f64(1.0 / self.tick_rate);
+    }
+
+    pub fn get_frame_duration(&self) -> Option<Duration> {
+        return self.max_fps.map(|fps| Duration::from_secs_f64(1.0 / fps));
+    }
+
+    pub fn is_wait_until_dirty(&self) -> bool {
+        return self.wait_until_dirty;
+    }
+}
+
+impl Default for LoopConfig {
+    fn default() -> LoopConfig {
+        return LoopConfig::new();
+    }
+}
+
+// A window opened with AppContext::open_window. It has a surface, Pipeline2D and root of its own,
+// the device, shaders, atlas and font are the main window's.
+pub struct AppWindow {
+    pub r_state: WgpuState,
+    pub pipeline_2d: Pipeline2D,
+    pub root: SimpleDrawable2D,
+    redraw_requested: bool,
+    last_frame: Option<Instant>, // Every window keeps to the frame cap on its own
+}
+
+impl AppWindow {
+    fn new(gpu: Rc<GpuContext>, window: Window) -> Result<AppWindow, RendererError> {
+        let r_state = WgpuState::with_context(gpu, Arc::new(window))?;
+        let mut root = SimpleDrawable2D::new();
+        let size = r_state.get_logical_size();
+        root.set_abs_size(size.x, size.y);
+        let pipeline_2d = Pipeline2D::new(&r_state, &mut root);
+        return Ok(AppWindow {
+            r_state,
+            pipeline_2d,
+            root,
+            redraw_requested: true,
+            last_frame: None,
+        });
+    }
+
+    // Draws the window's root drawable into the frame.
+    pub fn draw_root(&mut self, frame: &mut Frame) {
+        let (encoder, view) = frame.parts();
+        self.pipeline_2d.draw(&mut self.r_state, encoder, view, &mut self.root);
+    }
+
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if resize_surface(&mut self.r_state, &mut self.pipeline_2d, &mut self.root, new_size) {
+            self.request_redraw();
+        }
+    }
+}
+
+// Everything the runner owns that an app gets to use. The root drawable always covers the window, in logical pixels.
+pub struct AppContext {
+    pub r_state: WgpuState,
+    pub pipeline_2d: Pipeline2D,
+    pub root: SimpleDrawable2D,
+    pub windows: HashMap<WindowId, AppWindow>, // Opened with open_window, the main window isn't in here
+    pending_windows: Vec<WindowBuilder>,
+    exit_requested: bool,
+    redraw_requested: bool,
+    continuous_updates: bool,
+}
+
+impl AppContext {
+    // Draws the root drawable into the frame.
+    pub fn draw_root(&mut self, frame: &mut Frame) {
+        let (encoder, view) = frame.parts();
+        self.pipeline_2d.draw(&mut self.r_state, encoder, view, &mut self.root);
+    }
+
+    // The loop stops after the current event, App::exit is still called.
+    pub fn request_exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    // Draws the next frame even if the drawable tree didn't change, for things like camera moves when waiting until dirty.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    // Keeps the simulation ticking while waiting until dirty even when nothing needs to be drawn,
+    // for apps that move things in update. Has no effect otherwise, the loop always ticks then.
+    pub fn set_continuous_updates(&mut self, enabled: bool) {
+        self.continuous_updates = enabled;
+    }
+
+    // Opens another window once the current event is handled, App::window_opened is called with its id.
+    pub fn open_window(&mut self, builder: WindowBuilder) {
+        self.pending_windows.push(builder);
+    }
+
+    // Closes a window opened with open_window, App::window_closed isn't called for it.
+    pub fn close_window(&mut self, id: WindowId) {
+        self.windows.remove(&id);
+    }
+}
+
+// A program run by run(). Only init is required, everything else has a default that does the obvious thing.
+pub trait App: 'static {
+    // Called once the window and renderer exist, add drawables to ctx.root here. Sizes and positions are in logical pixels.
+    fn init(ctx: &mut AppContext) -> Self where Self: Sized;
+
+    // Called at the fixed tick rate, dt is always the tick length in seconds. The root drawable's update is called after this.
+    fn update(&mut self, _ctx: &mut AppContext, _dt: f32) {}
+
+    // Every window event the runner doesn't handle itself, which is all of them besides closing. Positions in events are
+    // in physical pixels, ctx.r_state.to_logical turns them into the tree's.
+    fn input(&mut self, _ctx: &mut AppContext, _event: &WindowEvent) {}
+
+    // Alpha is how far into the next tick the frame is, from 0 to 1, to interpolate between the last two ticks with.
+    fn draw(&mut self, ctx: &mut AppContext, frame: &mut Frame, _alpha: f32) {
+        ctx.draw_root(frame);
+    }
+
+    // The window changed size, the surface and Pipeline2D have already been updated. Not called while minimised.
+    fn resized(&mut self, _ctx: &mut AppContext, _size: PhysicalSize<u32>) {}
+
+    // The window moved to a display with a different DPI, or its scale setting changed. The root has already been resized to the new logical size.
+    fn scale_factor_changed(&mut self, _ctx: &mut AppContext, _scale_factor: f64) {}
+
+    // A window from AppContext::open_window is ready, add drawables to its root here.
+    fn window_opened(&mut self, _ctx: &mut AppContext, _id: WindowId) {}
+
+    // Like input, for the windows in ctx.windows. The runner handles their closing and resizing itself.
+    fn window_input(&mut self, _ctx: &mut AppContext, _id: WindowId, _event: &WindowEvent) {}
+
+    // Like draw, for the windows in ctx.windows.
+    fn draw_window(&mut self, ctx: &mut AppContext, id: WindowId, frame: &mut Frame, _alpha: f32) {
+        if let Some(window) = ctx.windows.get_mut(&id) {
+            window.draw_root(frame);
+        }
+    }
+
+    // One of the windows in ctx.windows was closed by the user or can't be drawn to anymore, it's dropped right after.
+    // Closing the main window exits instead.
+    fn window_closed(&mut self, _ctx: &mut AppContext, _id: WindowId) {}
+
+    // The loop is about to stop.
+    fn exit(&mut self, _ctx: &mut AppContext) {}
+}
+
+pub fn run<A: App>() -> Result<(), RendererError> {
+    return run_with_config::<A>(RendererConfig::new(), LoopConfig::new());
+}
+
+// Opens the window and runs the app until it's closed. Only returns if setting up fails.
+pub fn run_with_config<A: App>(renderer_config: RendererConfig, loop_config: LoopConfig) -> Result<(), RendererError> {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT))
+        .build(&event_loop)
+        .map_err(|err| RendererError::WindowCreationFailed(err.to_string()))?;
+    let r_state = pollster::block_on(WgpuState::with_config(Arc::new(window), renderer_config))?;
+
+    // Point SHADER_DIR at src/renderer to edit shaders while the app runs.
+    if let Ok(dir) = std::env::var("SHADER_DIR") {
+        r_state.gpu.shader_modules.borrow_mut().enable_hot_reload(&r_state.gpu.device, dir);
+    }
+
+    let mut root = SimpleDrawable2D::new();
+    let size = r_state.get_logical_size();
+    root.set_abs_size(size.x, size.y);
+    let pipeline_2d = Pipeline2D::new(&r_state, &mut root);
+    let mut ctx = AppContext {
+        r_state,
+        pipeline_2d,
+        root,
+        windows: HashMap::new(),
+        pending_windows: vec![],
+        exit_requested: false,
+        redraw_requested: true,
+        continuous_updates: false,
+    };
+    let mut app = A::init(&mut ctx);
+
+    let tick = loop_config.get_tick_duration();
+    let frame_duration = loop_config.get_frame_duration();
+    let mut accumulator = Duration::ZERO; // Time the simulation is behind by
+    let mut last_time = Instant::now();
+    let mut last_frame: Option<Instant> = None; // The main window's, the others keep their own
+    let mut idle = false; // Sleeping until an event while waiting until dirty, that time isn't simulated
+
+    event_loop.run(move |event, target, control_flow| {
+        match event {
+            Event::WindowEvent { window_id, event } if window_id != ctx.r_state.window.id() => match event {
+                WindowEvent::CloseRequested => {
+                    app.window_closed(&mut ctx, window_id);
+                    ctx.windows.remove(&window_id);
+                }
+                WindowEvent::Resized(new_size) => {
+                    if let Some(window) = ctx.windows.get_mut(&window_id) {
+                        window.resize(new_size);
+                    }
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    if let Some(window) = ctx.windows.get_mut(&window_id) {
+                        window.resize(*new_inner_size);
+                    }
+                }
+                event => app.window_input(&mut ctx, window_id, &event),
+            },
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => ctx.request_exit(),
+                WindowEvent::Resized(new_size) => resize(&mut ctx, &mut app, new_size),
+                WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                    // Winit suggests a size that keeps the logical size the same, which is what's used.
+                    resize(&mut ctx, &mut app, *new_inner_size);
+                    app.scale_factor_changed(&mut ctx, scale_factor);
+                }
+                event => app.input(&mut ctx, &event),
+            },
+            Event::MainEventsCleared => {
+                if ctx.r_state.gpu.shader_modules.borrow_mut().poll_hot_reload(&ctx.r_state.gpu.device) {
+                    ctx.request_redraw();
+                    for window in ctx.windows.values_mut() {
+                        window.request_redraw();
+                    }
+                }
+
+                let now = Instant::now();
+                if !idle {
+                    accumulator += now - last_time;
+                }
+                last_time = now;
+                let mut ticks = 0;
+                while accumulator >= tick {
+                    app.update(&mut ctx, tick.as_secs_f32());
+                    ctx.root.update(tick.as_secs_f32());
+                    for window in ctx.windows.values_mut() {
+                        window.root.update(tick.as_secs_f32());
+                    }
+                    accumulator -= tick;
+                    ticks += 1;
+                    if ticks == MAX_TICKS_PER_FRAME {
+                        accumulator = Duration::ZERO;
+                        break;
+                    }
+                }
+
+                let waits = loop_config.is_wait_until_dirty();
+                // Every window that wants a frame gets one, each as often as the frame cap allows.
+                let mut wants_frame = false;
+                let mut capped_until: Option<Instant> = None;
+                if !waits || ctx.redraw_requested || ctx.root.is_tree_dirty() {
+                    wants_frame = true;
+                    capped_until = schedule_frame(&ctx.r_state.window, frame_duration, last_frame, now, capped_until);
+                }
+                for window in ctx.windows.values_mut() {
+                    if !waits || window.redraw_requested || window.root.is_tree_dirty() {
+                        wants_frame = true;
+                        capped_until = schedule_frame(&window.r_state.window, frame_duration, window.last_frame, now, capped_until);
+                    }
+                }
+
+                // Sleep until the next tick, or the next frame if one is waiting on the frame cap.
+                // Waiting until dirty with nothing to draw sleeps until an event comes in.
+                let next_tick = now + (tick - accumulator);
+                idle = waits && !wants_frame && !ctx.continuous_updates;
+                *control_flow = if !waits && frame_duration.is_none() {
+                    ControlFlow::Poll
+                } else if idle {
+                    ControlFlow::Wait
+                } else if let Some(capped_until) = capped_until {
+                    ControlFlow::WaitUntil(next_tick.min(capped_until))
+                } else {
+                    ControlFlow::WaitUntil(next_tick)
+                };
+            }
+            Event::RedrawRequested(window_id) if window_id != ctx.r_state.window.id() => {
+                let frame = match ctx.windows.get_mut(&window_id) {
+                    Some(window) => window.r_state.begin_frame(),
+                    None => Ok(None),
+                };
+                match frame {
+                    Ok(Some(mut frame)) => {
+                        let alpha = (accumulator.as_secs_f64() / tick.as_secs_f64()) as f32;
+                        app.draw_window(&mut ctx, window_id, &mut frame, alpha);
+                        frame.finish();
+                        if let Some(window) = ctx.windows.get_mut(&window_id) {
+                            window.root.clear_dirty();
+                            window.redraw_requested = false;
+                            window.last_frame = Some(Instant::now());
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(err) => {
+                        // Only this window's surface is lost, the others and the device are still fine.
+                        log::error!("Closing a window that can't be drawn to anymore: {}", err);
+                        app.window_closed(&mut ctx, window_id);
+                        ctx.windows.remove(&window_id);
+                    }
+                }
+            }
+            Event::RedrawRequested(_) => {
+                match ctx.r_state.begin_frame() {
+                    Ok(Some(mut frame)) => {
+                        let alpha = (accumulator.as_secs_f64() / tick.as_secs_f64()) as f32;
+                        app.draw(&mut ctx, &mut frame, alpha);
+                        ctx.r_state.end_frame(frame);
+                        ctx.root.clear_dirty();
+                        ctx.redraw_requested = false;
+                        last_frame = Some(Instant::now());
+                    }
+                    Ok(None) => (),
+                    Err(err) => {
+                        log::error!("{}", err);
+                        ctx.request_exit();
+                    }
+                }
+            }
+            Event::LoopDestroyed => app.exit(&mut ctx),
+            _ => (),
+        }
+
+        // Windows asked for while handling the event are opened now that there's a target to open them on.
+        for builder in std::mem::take(&mut ctx.pending_windows) {
+            let opened = builder.build(target)
+                .map_err(|err| RendererError::WindowCreationFailed(err.to_string()))
+                .and_then(|window| AppWindow::new(ctx.r_state.gpu.clone(), window));
+            match opened {
+                Ok(window) => {
+                    let id = window.r_state.window.id();
+                    ctx.windows.insert(id, window);
+                    app.window_opened(&mut ctx, id);
+                }
+                Err(err) => log::error!("Couldn't open a window: {}", err),
+            }
+        }
+
+        if ctx.exit_requested {
+            *control_flow = ControlFlow::Exit;
+        }
+    });
+}
+
+// Asks for a redraw if the window's frame cap allows one now, otherwise returns the earliest time one is waiting for.
+fn schedule_frame(window: &Window, frame_duration: Option<Duration>, last_frame: Option<Instant>, now: Instant, capped_until: Option<Instant>) -> Option<Instant> {
+    let next_frame = match (frame_duration, last_frame) {
+        (Some(duration), Some(last)) => last + duration,
+        _ => now,
+    };
+    if next_frame <= now {
+        window.request_redraw();
+        return capped_until;
+    }
+    return Some(capped_until.map_or(next_frame, |capped_until| capped_until.min(next_frame)));
+}
+
+fn resize<A: App>(ctx: &mut AppContext, app: &mut A, new_size: PhysicalSize<u32>) {
+    if resize_surface(&mut ctx.r_state, &mut ctx.pipeline_2d, &mut ctx.root, new_size) {
+        app.resized(ctx, new_size);
+        ctx.request_redraw();
+    }
+}
+
+// The root is laid out again at the new logical size, everything inside it reflows from there.
+// Returns false for a minimised window, nothing is drawn to it until it has a size again.
+fn resize_surface(r_state: &mut WgpuState, pipeline_2d: &mut Pipeline2D, root: &mut SimpleDrawable2D, new_size: PhysicalSize<u32>) -> bool {
+    if !r_state.resize(new_size) {
+        return false;
+    }
+    let scale_factor = r_state.get_scale_factor();
+    root.set_abs_size(new_size.width as f32 / scale_factor, new_size.height as f32 / scale_factor);
+    pipeline_2d.on_resized(new_size.width, new_size.height, &r_state.gpu.device);
+    return true;
+}
\ No newline at end of file
@@ -1,10 +1,11 @@
 use crate::math::color::Color;
 
-use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, QuadDrawNode2D}};
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, QuadDrawNode2D, NodeInfo2D}};
 
 pub struct Box2D {
     state: DrawableState2D,
     color: Color,
+    corner_radius: f32,
 }
 
 impl Box2D {
@@ -12,6 +13,7 @@ impl Box2D {
         return Box2D {
             state: DrawableState2D::new(),
             color: Color::new(255, 255, 255, 255),
+            corner_radius: 0.0,
         }
     }
 
@@ -20,6 +22,13 @@ impl Box2D {
         self.state.set_dirty(true);
         return self;
     }
+
+    // Rounds all four corners, in pixels. 0 keeps them square.
+    pub fn set_corner_radius(&mut self, radius: f32) -> &mut Self {
+        self.corner_radius = radius.max(0.0);
+        self.state.set_dirty(true);
+        return self;
+    }
 }
 
 impl Drawable2D for Box2D {
@@ -29,8 +38,10 @@ impl Drawable2D for Box2D {
 
     fn draw(&mut self) -> Option<Vec<super::drawable_state_2d::DrawNode2D>> {
         let node = DrawNode2D::Quad(QuadDrawNode2D {
-            quad: self.state.get_transform_state().clone(),
-            color: self.color.to_vec4(),
+            info: NodeInfo2D::new(self.state.get_id()),
+            quad: *self.state.get_transform_state(),
+            color: self.color.to_linear_vec4(),
+            corner_radius: self.corner_radius,
         });
         return Some(vec![node]);
     }
@@ -0,0 +1,148 @@
+use cgmath::{Matrix4, Vector2, Vector3, Vector4, Rad, SquareMatrix};
+
+// A view over world space drawables: world points are rotated, zoomed and then panned in logical window pixels.
+#[derive(Copy, Clone)]
+pub struct Camera2D {
+    pub pan: Vector2<f32>,
+    pub zoom: f32,
+    pub rotation: f32, // Radians
+}
+
+impl Camera2D {
+    pub fn new() -> Camera2D {
+        return Camera2D {
+            pan: Vector2::new(0.0, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+
+    pub fn set_pan(&mut self, x: f32, y: f32) {
+        self.pan = Vector2::new(x, y);
+    }
+
+    pub fn pan_by(&mut self, x: f32, y: f32) {
+        self.pan += Vector2::new(x, y);
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    // Zooms by a factor while keeping the world point under the given window point in place.
+    pub fn zoom_at(&mut self, x: f32, y: f32, factor: f32) {
+        let world = self.screen_to_world(x, y);
+        self.zoom *= factor;
+        self.keep_in_place(world, x, y);
+    }
+
+    // Rotates by an angle around the world point under the given window point.
+    pub fn rotate_at(&mut self, x: f32, y: f32, angle: f32) {
+        let world = self.screen_to_world(x, y);
+        self.rotation += angle;
+        self.keep_in_place(world, x, y);
+    }
+
+    pub fn to_view_matrix(&self) -> Matrix4<f32> {
+        return Matrix4::from_translation(Vector3::new(self.pan.x, self.pan.y, 0.0))
+            * Matrix4::from_scale(self.zoom)
+            * Matrix4::from_angle_z(Rad(self.rotation));
+    }
+
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Vector2<f32> {
+        let inverse = self.to_view_matrix().invert().unwrap_or(Matrix4::identity());
+        let world = inverse * Vector4::new(x, y, 0.0, 1.0);
+        return Vector2::new(world.x, world.y);
+    }
+
+    pub fn world_to_screen(&self, x: f32, y: f32) -> Vector2<f32> {
+        let screen = self.to_view_matrix() * Vector4::new(x, y, 0.0, 1.0);
+        return Vector2::new(screen.x, screen.y);
+    }
+
+    fn keep_in_place(&mut self, world: Vector2<f32>, x: f32, y: f32) {
+        let moved = self.world_to_screen(world.x, world.y);
+        self.pan += Vector2::new(x - moved.x, y - moved.y);
+    }
+
+    // Maps logical window pixels (Y-down) to wgpu's -1 to 1 range.
+    pub fn screen_projection(window_size: Vector2<f32>) -> Matrix4<f32> {
+        return Matrix4::new(
+            2.0 / window_size.x, 0.0, 0.0, 0.0,
+            0.0, -2.0 / window_size.y, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -1.0, 1.0, 0.0, 1.0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn assert_close(value: Vector2<f32>, expected: Vector2<f32>) {
+        assert!((value - expected).x.abs() < 1e-3 && (value - expected).y.abs() < 1e-3, "{:?} isn't {:?}", value, expected);
+    }
+
+    fn camera(pan: Vector2<f32>, zoom: f32, rotation: f32) -> Camera2D {
+        let mut camera = Camera2D::new();
+        camera.set_pan(pan.x, pan.y);
+        camera.set_zoom(zoom);
+        camera.set_rotation(rotation);
+        return camera;
+    }
+
+    #[test]
+    fn screen_and_world_points_round_trip() {
+        let camera = camera(Vector2::new(120.0, -40.0), 2.5, 0.7);
+        for (x, y) in [(0.0, 0.0), (640.0, 360.0), (-25.0, 900.0)] {
+            let world = camera.screen_to_world(x, y);
+            assert_close(camera.world_to_screen(world.x, world.y), Vector2::new(x, y));
+            let screen = camera.world_to_screen(x, y);
+            assert_close(camera.screen_to_world(screen.x, screen.y), Vector2::new(x, y));
+        }
+    }
+
+    #[test]
+    fn world_points_are_rotated_then_zoomed_then_panned() {
+        let camera = camera(Vector2::new(100.0, 50.0), 2.0, FRAC_PI_2);
+        // (10, 0) turns a quarter clockwise on screen to (0, 10), doubles and moves with the pan.
+        assert_close(camera.world_to_screen(10.0, 0.0), Vector2::new(100.0, 70.0));
+    }
+
+    #[test]
+    fn zoom_at_keeps_the_point_under_the_cursor() {
+        let mut camera = camera(Vector2::new(30.0, 10.0), 1.5, 0.3);
+        let anchor = camera.screen_to_world(400.0, 250.0);
+        camera.zoom_at(400.0, 250.0, 3.0);
+        assert_close(camera.world_to_screen(anchor.x, anchor.y), Vector2::new(400.0, 250.0));
+        assert!((camera.zoom - 4.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotate_at_keeps_the_point_under_the_cursor() {
+        let mut camera = camera(Vector2::new(30.0, 10.0), 2.0, 0.0);
+        let anchor = camera.screen_to_world(200.0, 120.0);
+        camera.rotate_at(200.0, 120.0, 1.2);
+        assert_close(camera.world_to_screen(anchor.x, anchor.y), Vector2::new(200.0, 120.0));
+        assert!((camera.rotation - 1.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn the_screen_projection_maps_the_window_to_clip_space() {
+        let projection = Camera2D::screen_projection(Vector2::new(800.0, 600.0));
+        let corner = |x: f32, y: f32| {
+            let clip = projection * Vector4::new(x, y, 0.0, 1.0);
+            return Vector2::new(clip.x, clip.y);
+        };
+        assert_close(corner(0.0, 0.0), Vector2::new(-1.0, 1.0));
+        assert_close(corner(800.0, 600.0), Vector2::new(1.0, -1.0));
+        assert_close(corner(400.0, 300.0), Vector2::new(0.0, 0.0));
+    }
+}
\ No newline at end of file
@@ -1,10 +1,8 @@
-use std::{any::TypeId, sync::{Arc, atomic::{AtomicI64, AtomicU64}}, marker::PhantomData, rc::Rc, cell::RefCell};
+use crate::{math::color::Color, renderer::blend_mode::BlendMode};
 
-use cgmath::{Vector2, Vector4};
+use super::material_2d::MaterialId;
 
-use crate::math::color::Color;
-
-use super::{drawable_state_2d::{DrawableState2D, DrawNode2D, DrawableId, TransformState2D, Anchor}, box_2d::Box2D};
+use super::{drawable_state_2d::{DrawableState2D, DrawNode2D, DrawableId, TransformState2D, Anchor, QuadDrawNode2D, ImageDrawNode2D, MeshDrawNode2D, LayerDrawNode2D, BackdropDrawNode2D, HeatMapDrawNode2D, NodeInfo2D, Affine2D, AffineFrame2D}, box_2d::Box2D};
 
 pub struct SimpleDrawable2D {
     state: DrawableState2D,
@@ -35,9 +33,10 @@ pub trait Drawable2D {
         return self.get_state().get_id();
     } 
 
-    fn update(&mut self) {
+    // Delta is the time since the last update, in seconds.
+    fn update(&mut self, delta: f32) {
         for child in self.get_state().get_children() {
-            child.update();
+            child.update(delta);
         }
     }
 
@@ -52,32 +51,12 @@ pub trait Drawable2D {
         // Relative values go down the line if not found
         for child in children {
             let result = child.draw();
-            if !result.is_some() {
+            if result.is_none() {
                 continue;
             }
-            let mut new_nodes = result.expect("");
-            //println!("sup");
-            for mut node in &mut new_nodes {
-                let mut transform: &mut TransformState2D;
-                match node {
-                    DrawNode2D::Quad(quad) => {
-                        transform = &mut quad.quad;
-                        // Apply the size information we know about ourselves and our child to the child.
-                        transform.abs_size.x += transform.rel_size.x * self_state.abs_size.x;
-                        transform.abs_size.y += transform.rel_size.y * self_state.abs_size.y;
-                        transform.rel_size = self_state.rel_size;
-                    }
-                    DrawNode2D::Text(text) => {
-                        transform = &mut text.transform;
-                    }
-                }
-
-                process_anchors(&mut transform);
-                // Apply the position information we know about ourselves and our child to the child.
-                transform.abs_pos.x += transform.rel_pos.x * self_state.abs_size.x;
-                transform.abs_pos.y += transform.rel_pos.y * self_state.abs_size.y;
-                transform.rel_pos = self_state.rel_pos;
-                transform.abs_pos += self_state.abs_pos;
+            let mut new_nodes = apply_drawable_state(child.as_mut(), result.expect(""));
+            for node in &mut new_nodes {
+                resolve_node(node, self_state);
             }
             nodes.append(&mut new_nodes);
         }
@@ -92,6 +71,21 @@ pub trait Drawable2D {
         self.get_state().set_dirty(dirty);
     }
 
+    // Whether the drawable or anything below it changed since clear_dirty was last called.
+    fn is_tree_dirty(&mut self) -> bool {
+        if self.is_dirty() {
+            return true;
+        }
+        return self.get_state().get_children().iter_mut().any(|child| child.is_tree_dirty());
+    }
+
+    fn clear_dirty(&mut self) {
+        self.set_dirty(false);
+        for child in self.get_state().get_children() {
+            child.clear_dirty();
+        }
+    }
+
     fn set_abs_pos(&mut self, x: f32, y: f32) {
         self.get_state().set_abs_pos(x, y);
     }
@@ -116,6 +110,34 @@ pub trait Drawable2D {
         self.get_state().set_alignment(alignment);
     }
 
+    fn set_affine(&mut self, affine: Option<Affine2D>) {
+        self.get_state().set_affine(affine);
+    }
+
+    fn set_world_space(&mut self, world_space: bool) {
+        self.get_state().set_world_space(world_space);
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: Option<BlendMode>) {
+        self.get_state().set_blend_mode(blend_mode);
+    }
+
+    fn set_opacity(&mut self, opacity: f32) {
+        self.get_state().set_opacity(opacity);
+    }
+
+    fn set_cached(&mut self, cached: bool) {
+        self.get_state().set_cached(cached);
+    }
+
+    fn set_backdrop_blur(&mut self, radius: f32) {
+        self.get_state().set_backdrop_blur(radius);
+    }
+
+    fn set_material(&mut self, material: Option<MaterialId>) {
+        self.get_state().set_material(material);
+    }
+
     fn get_transform_state(&mut self) -> &TransformState2D {
         return self.get_state().get_transform_state();
     }
@@ -129,6 +151,115 @@ pub trait Drawable2D {
     }
 }
 
+// Puts what a drawable's own state does to its nodes on them: world space, material and affine transform, the layer,
+// the backdrop and the blend mode. Parents do this for their children, Pipeline2D does it for the roots.
+pub fn apply_drawable_state(drawable: &mut dyn Drawable2D, nodes: Vec<DrawNode2D>) -> Vec<DrawNode2D> {
+    let mut new_nodes = nodes;
+    // The drawable's own rectangle is carried along with its affine transform so the pivot is resolved like any other quad.
+    let affine = drawable.get_state().get_affine();
+    let frame = *drawable.get_transform_state();
+    let world_space = drawable.get_state().is_world_space();
+    let blend_mode = drawable.get_state().get_blend_mode();
+    let material = drawable.get_state().get_material();
+    // Affine transforms, world space and materials end up in the layer texture, so they go on the nodes inside layers.
+    apply_to_leaves(&mut new_nodes, &mut |node| {
+        if world_space {
+            node.get_info_mut().world_space = true;
+        }
+        if node.get_info().material.is_none() {
+            node.get_info_mut().material = material;
+        }
+        if let Some(affine) = affine {
+            node.get_info_mut().affine.push(AffineFrame2D {
+                affine,
+                frame,
+            });
+        }
+    });
+    if drawable.get_state().needs_layer() {
+        new_nodes = vec![DrawNode2D::Layer(LayerDrawNode2D {
+            info: NodeInfo2D::new(drawable.get_id()),
+            nodes: new_nodes,
+            opacity: drawable.get_state().get_opacity(),
+            cached: drawable.get_state().is_cached(),
+        })];
+    }
+    // The backdrop goes outside any layer so it blurs what's behind the drawable, not its children.
+    let backdrop_blur = drawable.get_state().get_backdrop_blur();
+    if backdrop_blur > 0.0 {
+        let mut info = NodeInfo2D::new(drawable.get_id());
+        info.world_space = world_space;
+        if let Some(affine) = affine {
+            info.affine.push(AffineFrame2D {
+                affine,
+                frame,
+            });
+        }
+        new_nodes.insert(0, DrawNode2D::Backdrop(BackdropDrawNode2D {
+            info,
+            transform: frame,
+            radius: backdrop_blur,
+        }));
+    }
+    // Nodes inside a layer blend with the layer, the layer itself blends with what's below it.
+    for node in &mut new_nodes {
+        if node.get_info().blend_mode.is_none() {
+            node.get_info_mut().blend_mode = blend_mode;
+        }
+    }
+    return new_nodes;
+}
+
+fn apply_to_leaves(nodes: &mut Vec<DrawNode2D>, f: &mut dyn FnMut(&mut DrawNode2D)) {
+    for node in nodes {
+        match node {
+            DrawNode2D::Layer(layer) => apply_to_leaves(&mut layer.nodes, f),
+            _ => f(node),
+        }
+    }
+}
+
+// Resolves one level of a node's transform and affine frames against its parent.
+fn resolve_node(node: &mut DrawNode2D, self_state: &TransformState2D) {
+    match node {
+        DrawNode2D::Quad(QuadDrawNode2D { quad: transform, info, .. }) | DrawNode2D::Image(ImageDrawNode2D { transform, info, .. }) | DrawNode2D::Mesh(MeshDrawNode2D { transform, info, .. })
+            | DrawNode2D::Backdrop(BackdropDrawNode2D { transform, info, .. }) | DrawNode2D::HeatMap(HeatMapDrawNode2D { transform, info, .. }) => {
+            resolve_transform(transform, self_state, true);
+            for affine_frame in &mut info.affine {
+                resolve_transform(&mut affine_frame.frame, self_state, true);
+            }
+        }
+        DrawNode2D::Text(text) => {
+            resolve_transform(&mut text.transform, self_state, false);
+            for affine_frame in &mut text.info.affine {
+                resolve_transform(&mut affine_frame.frame, self_state, true);
+            }
+        }
+        DrawNode2D::Layer(layer) => {
+            for node in &mut layer.nodes {
+                resolve_node(node, self_state);
+            }
+        }
+    }
+}
+
+// Resolves one level of a child's relative values against its parent.
+fn resolve_transform(transform: &mut TransformState2D, self_state: &TransformState2D, resolve_size: bool) {
+    if resolve_size {
+        // Apply the size information we know about ourselves and our child to the child.
+        transform.abs_size.x += transform.rel_size.x * self_state.abs_size.x;
+        transform.abs_size.y += transform.rel_size.y * self_state.abs_size.y;
+        transform.rel_size = self_state.rel_size;
+    }
+
+    process_anchors(transform);
+    // Apply the position information we know about ourselves and our child to the child.
+    transform.abs_pos.x += transform.rel_pos.x * self_state.abs_size.x;
+    transform.abs_pos.y += transform.rel_pos.y * self_state.abs_size.y;
+    transform.rel_pos = self_state.rel_pos;
+    transform.abs_pos += self_state.abs_pos;
+}
+
 fn process_anchors(transform: &mut TransformState2D) {
     if transform.origin.intersects(Anchor::X1) {
         transform.rel_pos.x += 0.5;
@@ -142,19 +273,77 @@ fn process_anchors(transform: &mut TransformState2D) {
         transform.rel_pos.y += 1.0;
     }
 
-    if transform.alignment.intersects(Anchor::X1) {
-        transform.abs_pos.x -= transform.abs_size.x / 2.0;
-        transform.rel_pos.x -= transform.rel_size.x / 2.0;
-    } else if transform.alignment.intersects(Anchor::X2) {
-        transform.abs_pos.x -= transform.abs_size.x;
-        transform.rel_pos.x -= transform.rel_size.x;
+    transform.apply_alignment();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use cgmath::Vector2;
+
+    use super::*;
+
+    // A 10x10 box at (20, 20) with the affine transform, drawn as a root.
+    fn box_nodes(affine: Option<Affine2D>) -> Vec<DrawNode2D> {
+        let mut root = Box2D::new();
+        root.set_abs_pos(20.0, 20.0);
+        root.set_abs_size(10.0, 10.0);
+        root.set_affine(affine);
+        let nodes = root.draw().unwrap();
+        return apply_drawable_state(&mut root, nodes);
+    }
+
+    fn hits(nodes: &[DrawNode2D], x: f32, y: f32) -> bool {
+        return nodes.iter().any(|node| node.contains_point(Vector2::new(x, y)));
+    }
+
+    #[test]
+    fn a_root_without_an_affine_is_hit_inside_its_rectangle() {
+        let nodes = box_nodes(None);
+        assert!(hits(&nodes, 25.0, 25.0));
+        assert!(!hits(&nodes, 35.0, 25.0));
+    }
+
+    #[test]
+    fn the_affine_of_a_root_moves_its_hit_area() {
+        let nodes = box_nodes(Some(Affine2D::new().with_translation(100.0, 0.0)));
+        assert!(hits(&nodes, 125.0, 25.0));
+        assert!(!hits(&nodes, 25.0, 25.0));
+    }
+
+    #[test]
+    fn scaling_around_the_pivot_is_undone_for_hit_tests() {
+        let nodes = box_nodes(Some(Affine2D::new().with_scale(2.0, 2.0).with_pivot(Anchor::TOP_LEFT)));
+        assert!(hits(&nodes, 38.0, 38.0));
+        assert!(!hits(&nodes, 41.0, 25.0));
+        assert!(!hits(&nodes, 19.0, 25.0));
+    }
+
+    #[test]
+    fn rotation_is_undone_for_hit_tests() {
+        // Turned 45 degrees around its centre, the corners move in and the diamond's tips stick out past the sides.
+        let nodes = box_nodes(Some(Affine2D::new().with_rotation(FRAC_PI_4)));
+        assert!(hits(&nodes, 25.0, 25.0));
+        assert!(!hits(&nodes, 20.5, 20.5));
+        assert!(hits(&nodes, 31.5, 25.0));
+        assert!(!hits(&nodes, 31.5, 31.5));
     }
 
-    if transform.alignment.intersects(Anchor::Y1) {
-        transform.abs_pos.y -= transform.abs_size.y / 2.0;
-        transform.rel_pos.y -= transform.rel_size.y / 2.0;
-    } else if transform.alignment.intersects(Anchor::Y2) {
-        transform.abs_pos.y -= transform.abs_size.y;
-        transform.rel_pos.y -= transform.rel_size.y;
+    #[test]
+    fn parent_and_child_affines_compose() {
+        let mut child = Box2D::new();
+        child.set_abs_size(10.0, 10.0);
+        child.set_affine(Some(Affine2D::new().with_translation(5.0, 0.0)));
+        let mut root = SimpleDrawable2D { state: DrawableState2D::new() };
+        root.set_abs_pos(20.0, 20.0);
+        root.set_abs_size(10.0, 10.0);
+        root.set_affine(Some(Affine2D::new().with_translation(0.0, 50.0)));
+        root.add_child(Box::new(child));
+        let nodes = root.draw().unwrap();
+        let nodes = apply_drawable_state(&mut root, nodes);
+        assert!(hits(&nodes, 34.0, 75.0));
+        assert!(!hits(&nodes, 24.0, 75.0));
+        assert!(!hits(&nodes, 34.0, 25.0));
     }
 }
\ No newline at end of file
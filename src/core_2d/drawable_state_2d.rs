@@ -1,11 +1,13 @@
 use std::sync::atomic::AtomicU64;
 
 use bitflags::bitflags;
-use cgmath::{Vector4, Vector2};
+use cgmath::{Vector4, Vector2, Vector3, Matrix3, Rad, SquareMatrix};
 
-use crate::math::color::Color;
+use std::rc::Rc;
 
-use super::drawable_2d::Drawable2D;
+use crate::{math::{color::Color, tessellation::Mesh2D}, renderer::{texture_atlas::AtlasImageId, blend_mode::BlendMode}};
+
+use super::{drawable_2d::Drawable2D, material_2d::MaterialId, heat_map_2d::HeatMapGrid2D};
 
 static DRAWABLE_STATE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -57,7 +59,7 @@ impl Anchor {
     static BOTTOM_RIGHT: AnchorBit = AnchorBit::Y2 |AnchorBit::X2;
 }*/
 
-#[derive(Copy, Clone, PartialEq, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct DrawableId(u64);
 
 impl DrawableId {
@@ -69,14 +71,246 @@ impl DrawableId {
 pub enum DrawNode2D {
     Quad(QuadDrawNode2D),
     Text(TextDrawNode2D),
+    Image(ImageDrawNode2D),
+    Mesh(MeshDrawNode2D),
+    Layer(LayerDrawNode2D),
+    Backdrop(BackdropDrawNode2D),
+    HeatMap(HeatMapDrawNode2D),
+}
+
+impl DrawNode2D {
+    pub fn get_info(&self) -> &NodeInfo2D {
+        return match self {
+            DrawNode2D::Quad(quad) => &quad.info,
+            DrawNode2D::Text(text) => &text.info,
+            DrawNode2D::Image(image) => &image.info,
+            DrawNode2D::Mesh(mesh) => &mesh.info,
+            DrawNode2D::Layer(layer) => &layer.info,
+            DrawNode2D::Backdrop(backdrop) => &backdrop.info,
+            DrawNode2D::HeatMap(heat_map) => &heat_map.info,
+        };
+    }
+
+    pub fn get_info_mut(&mut self) -> &mut NodeInfo2D {
+        return match self {
+            DrawNode2D::Quad(quad) => &mut quad.info,
+            DrawNode2D::Text(text) => &mut text.info,
+            DrawNode2D::Image(image) => &mut image.info,
+            DrawNode2D::Mesh(mesh) => &mut mesh.info,
+            DrawNode2D::Layer(layer) => &mut layer.info,
+            DrawNode2D::Backdrop(backdrop) => &mut backdrop.info,
+            DrawNode2D::HeatMap(heat_map) => &mut heat_map.info,
+        };
+    }
+
+    // Checks a point in window pixels against the node's rectangle, with its affine transforms undone.
+    // Text has no known size so it can't be hit, layers are tested through their nodes with flatten_nodes.
+    // Backdrops sit behind the drawable that asked for them, so they're never hit either.
+    pub fn contains_point(&self, point: Vector2<f32>) -> bool {
+        let inverse = match self.get_info().get_matrix().invert() {
+            Some(inverse) => inverse,
+            None => return false, // Scaled down to nothing
+        };
+        let local = inverse * Vector3::new(point.x, point.y, 1.0);
+        let transform = match self {
+            DrawNode2D::Quad(quad) => &quad.quad,
+            DrawNode2D::Image(image) => &image.transform,
+            DrawNode2D::HeatMap(heat_map) => &heat_map.transform,
+            DrawNode2D::Mesh(mesh) => return mesh.contains_point(Vector2::new(local.x, local.y)),
+            DrawNode2D::Text(_) | DrawNode2D::Layer(_) | DrawNode2D::Backdrop(_) => return false,
+        };
+        return local.x >= transform.abs_pos.x && local.x <= transform.abs_pos.x + transform.abs_size.x
+            && local.y >= transform.abs_pos.y && local.y <= transform.abs_pos.y + transform.abs_size.y;
+    }
+}
+
+// Collects the nodes that actually draw something, in drawing order, looking inside layers.
+pub fn flatten_nodes<'a>(nodes: &'a [DrawNode2D], out: &mut Vec<&'a DrawNode2D>) {
+    for node in nodes {
+        match node {
+            DrawNode2D::Layer(layer) => flatten_nodes(&layer.nodes, out),
+            _ => out.push(node),
+        }
+    }
+}
+
+// Information every kind of node carries besides what it draws.
+#[derive(Clone)]
+pub struct NodeInfo2D {
+    pub id: DrawableId, // The drawable that emitted the node
+    pub affine: Vec<AffineFrame2D>, // Innermost drawable first
+    pub world_space: bool, // Drawn through the Pipeline2D camera instead of straight to the screen
+    pub blend_mode: Option<BlendMode>, // Set by the closest drawable that has one, Normal if none do
+    pub material: Option<MaterialId>, // Same as the blend mode, text ignores it
+}
+
+impl NodeInfo2D {
+    pub fn new(id: DrawableId) -> NodeInfo2D {
+        return NodeInfo2D {
+            id,
+            affine: vec![],
+            world_space: false,
+            blend_mode: None,
+            material: None,
+        }
+    }
+
+    // Combines the affine transforms of every drawable above the node, only valid once the frames are resolved.
+    pub fn get_matrix(&self) -> Matrix3<f32> {
+        let mut matrix = Matrix3::identity();
+        for frame in &self.affine {
+            matrix = frame.affine.to_matrix(&frame.frame) * matrix;
+        }
+        return matrix;
+    }
+}
+
+// An affine transform together with the rectangle of the drawable it belongs to,
+// the rectangle goes through the same layout steps as the nodes so the pivot ends up in the right place.
+#[derive(Copy, Clone)]
+pub struct AffineFrame2D {
+    pub affine: Affine2D,
+    pub frame: TransformState2D,
+}
+
+// Rotation, skew and scale happen around the pivot, translation is applied last. Angles are in radians.
+#[derive(Copy, Clone)]
+pub struct Affine2D {
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+    pub skew: Vector2<f32>,
+    pub translation: Vector2<f32>,
+    pub pivot: Anchor,
+}
+
+impl Affine2D {
+    pub fn new() -> Affine2D {
+        return Affine2D {
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+            skew: Vector2::new(0.0, 0.0),
+            translation: Vector2::new(0.0, 0.0),
+            pivot: Anchor::CENTRE,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        return self;
+    }
+
+    pub fn with_scale(mut self, x: f32, y: f32) -> Self {
+        self.scale = Vector2::new(x, y);
+        return self;
+    }
+
+    pub fn with_skew(mut self, x: f32, y: f32) -> Self {
+        self.skew = Vector2::new(x, y);
+        return self;
+    }
+
+    pub fn with_translation(mut self, x: f32, y: f32) -> Self {
+        self.translation = Vector2::new(x, y);
+        return self;
+    }
+
+    pub fn with_pivot(mut self, pivot: Anchor) -> Self {
+        self.pivot = pivot;
+        return self;
+    }
+
+    // The frame has to be fully resolved, i.e. all relative values already turned into pixels.
+    pub fn to_matrix(&self, frame: &TransformState2D) -> Matrix3<f32> {
+        let mut pivot = frame.abs_pos;
+        if self.pivot.intersects(Anchor::X1) {
+            pivot.x += frame.abs_size.x / 2.0;
+        } else if self.pivot.intersects(Anchor::X2) {
+            pivot.x += frame.abs_size.x;
+        }
+        if self.pivot.intersects(Anchor::Y1) {
+            pivot.y += frame.abs_size.y / 2.0;
+        } else if self.pivot.intersects(Anchor::Y2) {
+            pivot.y += frame.abs_size.y;
+        }
+
+        let skew = Matrix3::new(
+            1.0, self.skew.y.tan(), 0.0,
+            self.skew.x.tan(), 1.0, 0.0,
+            0.0, 0.0, 1.0,
+        );
+        return Matrix3::from_translation(self.translation + pivot)
+            * Matrix3::from_angle_z(Rad(self.rotation))
+            * skew
+            * Matrix3::from_nonuniform_scale(self.scale.x, self.scale.y)
+            * Matrix3::from_translation(-pivot);
+    }
 }
 
 pub struct QuadDrawNode2D {
+    pub info: NodeInfo2D,
     pub quad: TransformState2D,
-    pub color: Vector4<f32>
+    pub color: Vector4<f32>, // Linear, Color::to_linear_vec4 turns a Color into it
+    pub corner_radius: f32, // Pixels, 0 keeps the corners square
+}
+
+pub struct ImageDrawNode2D {
+    pub info: NodeInfo2D,
+    pub transform: TransformState2D,
+    pub image: AtlasImageId,
+    pub uv_rect: [f32; 4], // Part of the image to show, normalized as [x, y, width, height]
+    pub color: Vector4<f32>, // Linear
+    pub corner_radius: f32,
+}
+
+// Triangles in pixels relative to the transform's position, used for paths.
+pub struct MeshDrawNode2D {
+    pub info: NodeInfo2D,
+    pub transform: TransformState2D,
+    pub mesh: Rc<Mesh2D>,
+    pub color: Vector4<f32>, // Linear
+}
+
+impl MeshDrawNode2D {
+    fn contains_point(&self, point: Vector2<f32>) -> bool {
+        let p = point - self.transform.abs_pos;
+        let vertices = &self.mesh.vertices;
+        return self.mesh.indices.chunks(3).any(|triangle| {
+            let (a, b, c) = (vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]);
+            let d1 = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+            let d2 = (c.x - b.x) * (p.y - b.y) - (c.y - b.y) * (p.x - b.x);
+            let d3 = (a.x - c.x) * (p.y - c.y) - (a.y - c.y) * (p.x - c.x);
+            let has_negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+            let has_positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+            !(has_negative && has_positive)
+        });
+    }
+}
+
+// A subtree that is drawn into its own texture first and then composited in one go.
+// Affine transforms and world space are already applied to the nodes inside, only the blend mode is used for the composite.
+pub struct LayerDrawNode2D {
+    pub info: NodeInfo2D,
+    pub nodes: Vec<DrawNode2D>,
+    pub opacity: f32,
+    pub cached: bool, // The texture is only redrawn when the nodes inside change
+}
+
+// Blurs what has been drawn so far inside the rectangle, for frosted glass panels.
+pub struct BackdropDrawNode2D {
+    pub info: NodeInfo2D,
+    pub transform: TransformState2D,
+    pub radius: f32,
+}
+
+// A grid of values drawn through mesh.wgsl, stretched over the transform.
+pub struct HeatMapDrawNode2D {
+    pub info: NodeInfo2D,
+    pub transform: TransformState2D,
+    pub grid: Rc<HeatMapGrid2D>,
 }
 
 pub struct TextDrawNode2D {
+    pub info: NodeInfo2D,
     pub transform: TransformState2D,
     pub text: String,
     pub scale: f32,
@@ -106,11 +340,37 @@ impl TransformState2D {
             alignment: Anchor::TOP_LEFT,
         }
     }
+
+    // Moves the quad so that its alignment point sits on its position.
+    pub fn apply_alignment(&mut self) {
+        if self.alignment.intersects(Anchor::X1) {
+            self.abs_pos.x -= self.abs_size.x / 2.0;
+            self.rel_pos.x -= self.rel_size.x / 2.0;
+        } else if self.alignment.intersects(Anchor::X2) {
+            self.abs_pos.x -= self.abs_size.x;
+            self.rel_pos.x -= self.rel_size.x;
+        }
+
+        if self.alignment.intersects(Anchor::Y1) {
+            self.abs_pos.y -= self.abs_size.y / 2.0;
+            self.rel_pos.y -= self.rel_size.y / 2.0;
+        } else if self.alignment.intersects(Anchor::Y2) {
+            self.abs_pos.y -= self.abs_size.y;
+            self.rel_pos.y -= self.rel_size.y;
+        }
+    }
 }
 
 pub struct DrawableState2D {
     id: DrawableId,
     pub drawable_transform: TransformState2D,
+    pub affine: Option<Affine2D>,
+    pub world_space: bool,
+    pub blend_mode: Option<BlendMode>,
+    pub opacity: f32,
+    pub cached: bool,
+    pub backdrop_blur: f32,
+    pub material: Option<MaterialId>,
     pub children: Vec<Box<dyn Drawable2D>>,
     dirty: bool,// Whether the buffers for the drawable need to be recalculated
 }
@@ -120,13 +380,20 @@ impl DrawableState2D {
         return DrawableState2D {
             id: DrawableId(DRAWABLE_STATE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst)),
             drawable_transform: TransformState2D::new(),
+            affine: None,
+            world_space: false,
+            blend_mode: None,
+            opacity: 1.0,
+            cached: false,
+            backdrop_blur: 0.0,
+            material: None,
             children: vec![],
             dirty: true,
         };
     }
 
     pub fn get_id(&mut self) -> DrawableId {
-        return self.id.clone();
+        return self.id;
     }
 
     pub fn is_dirty(&mut self) -> bool {
@@ -167,6 +434,80 @@ impl DrawableState2D {
         self.set_dirty(true);
     }
 
+    pub fn set_affine(&mut self, affine: Option<Affine2D>) {
+        self.affine = affine;
+        self.set_dirty(true);
+    }
+
+    pub fn get_affine(&mut self) -> Option<Affine2D> {
+        return self.affine;
+    }
+
+    // World space drawables and everything below them move with the Pipeline2D camera.
+    pub fn set_world_space(&mut self, world_space: bool) {
+        self.world_space = world_space;
+        self.set_dirty(true);
+    }
+
+    pub fn is_world_space(&mut self) -> bool {
+        return self.world_space;
+    }
+
+    // None inherits the blend mode of the parent.
+    pub fn set_blend_mode(&mut self, blend_mode: Option<BlendMode>) {
+        self.blend_mode = blend_mode;
+        self.set_dirty(true);
+    }
+
+    pub fn get_blend_mode(&mut self) -> Option<BlendMode> {
+        return self.blend_mode;
+    }
+
+    // Applies to the drawable and its children as a group, so overlapping children don't show through each other.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self.set_dirty(true);
+    }
+
+    pub fn get_opacity(&mut self) -> f32 {
+        return self.opacity;
+    }
+
+    // Keeps the drawable and its children in a texture that is reused until they change, for subtrees that rarely do.
+    pub fn set_cached(&mut self, cached: bool) {
+        self.cached = cached;
+        self.set_dirty(true);
+    }
+
+    pub fn is_cached(&mut self) -> bool {
+        return self.cached;
+    }
+
+    // Blurs everything drawn before the drawable inside its rectangle, 0 turns it off. The radius is in pixels.
+    pub fn set_backdrop_blur(&mut self, radius: f32) {
+        self.backdrop_blur = radius.max(0.0);
+        self.set_dirty(true);
+    }
+
+    pub fn get_backdrop_blur(&mut self) -> f32 {
+        return self.backdrop_blur;
+    }
+
+    // A material registered with Pipeline2D, None inherits the material of the parent.
+    pub fn set_material(&mut self, material: Option<MaterialId>) {
+        self.material = material;
+        self.set_dirty(true);
+    }
+
+    pub fn get_material(&mut self) -> Option<MaterialId> {
+        return self.material;
+    }
+
+    // Whether the drawable has to be rendered into a texture of its own.
+    pub fn needs_layer(&mut self) -> bool {
+        return self.opacity < 1.0 || self.cached;
+    }
+
     pub fn get_transform_state(&mut self) -> &TransformState2D {
         return &mut self.drawable_transform;
     }
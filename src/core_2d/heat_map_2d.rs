@@ -0,0 +1,533 @@
+use std::{collections::HashMap, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Vector2, Vector3};
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, PipelineLayout, RenderPass, RenderPipeline, Texture};
+
+use crate::{math::color::Color, renderer::{WgpuState, blend_mode::BlendMode}};
+
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, DrawableId, HeatMapDrawNode2D, NodeInfo2D}};
+
+// mesh.wgsl has room for this many ramp stops.
+pub const MAX_RAMP_STOPS: usize = 8;
+// The values are uploaded as one texture, this is the max_texture_dimension_2d of wgpu's downlevel limits that every adapter has.
+pub const MAX_HEAT_MAP_SIZE: u32 = 2048;
+
+// Colors values are mapped to, stop positions go from 0 at the start of the range to 1 at the end.
+#[derive(Clone)]
+pub struct ColorRamp2D {
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp2D {
+    pub fn new(stops: Vec<(f32, Color)>) -> ColorRamp2D {
+        let mut stops = stops;
+        if stops.len() > MAX_RAMP_STOPS {
+            log::warn!("Color ramps can have at most {} stops, the rest are dropped", MAX_RAMP_STOPS);
+            stops.truncate(MAX_RAMP_STOPS);
+        }
+        if stops.is_empty() {
+            stops.push((0.0, Color::new(255, 255, 255, 255)));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        return ColorRamp2D {
+            stops,
+        }
+    }
+
+    pub fn heat() -> ColorRamp2D {
+        return ColorRamp2D::new(vec![
+            (0.0, Color::new(0, 0, 0, 255)),
+            (0.35, Color::new(200, 20, 0, 255)),
+            (0.7, Color::new(255, 200, 0, 255)),
+            (1.0, Color::new(255, 255, 255, 255)),
+        ]);
+    }
+
+    pub fn grayscale() -> ColorRamp2D {
+        return ColorRamp2D::new(vec![
+            (0.0, Color::new(0, 0, 0, 255)),
+            (1.0, Color::new(255, 255, 255, 255)),
+        ]);
+    }
+
+    pub fn viridis() -> ColorRamp2D {
+        return ColorRamp2D::new(vec![
+            (0.0, Color::new(68, 1, 84, 255)),
+            (0.25, Color::new(59, 82, 139, 255)),
+            (0.5, Color::new(33, 145, 140, 255)),
+            (0.75, Color::new(94, 201, 98, 255)),
+            (1.0, Color::new(253, 231, 37, 255)),
+        ]);
+    }
+
+    pub fn get_stops(&self) -> &Vec<(f32, Color)> {
+        return &self.stops;
+    }
+}
+
+// Everything the renderer needs from a HeatMap2D, shared with the draw node so values aren't copied every frame.
+#[derive(Clone)]
+pub struct HeatMapGrid2D {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<u32>, // Row by row, starting at the top left
+    pub range: [f32; 2],
+    pub ramp: ColorRamp2D,
+    pub wireframe: Option<Color>,
+    pub version: u64, // Bumped on every change so the texture is only uploaded when needed
+}
+
+// A grid of values drawn as colored cells through mesh.wgsl, stretched over the drawable's rectangle.
+pub struct HeatMap2D {
+    state: DrawableState2D,
+    grid: Rc<HeatMapGrid2D>,
+}
+
+impl HeatMap2D {
+    // Sizes above MAX_HEAT_MAP_SIZE are clamped.
+    pub fn new(width: u32, height: u32) -> HeatMap2D {
+        let (width, height, cells) = fit_grid(width, height);
+        return HeatMap2D {
+            state: DrawableState2D::new(),
+            grid: Rc::new(HeatMapGrid2D {
+                width,
+                height,
+                values: vec![0; cells],
+                range: [0.0, 255.0],
+                ramp: ColorRamp2D::heat(),
+                wireframe: None,
+                version: 0,
+            }),
+        }
+    }
+
+    pub fn set_values_u8(&mut self, values: &[u8]) -> &mut Self {
+        let values: Vec<u32> = values.iter().map(|value| *value as u32).collect();
+        return self.set_values_u32(&values);
+    }
+
+    // There has to be exactly one value per cell.
+    pub fn set_values_u32(&mut self, values: &[u32]) -> &mut Self {
+        if values.len() != self.grid.values.len() {
+            log::warn!("HeatMap2D got {} values for a grid of {} cells", values.len(), self.grid.values.len());
+            return self;
+        }
+        self.modify(|grid| grid.values.copy_from_slice(values));
+        return self;
+    }
+
+    pub fn set_value(&mut self, x: u32, y: u32, value: u32) -> &mut Self {
+        if x >= self.grid.width || y >= self.grid.height {
+            return self;
+        }
+        self.modify(|grid| grid.values[y as usize * grid.width as usize + x as usize] = value);
+        return self;
+    }
+
+    // Clears all values, sizes above MAX_HEAT_MAP_SIZE are clamped.
+    pub fn resize(&mut self, width: u32, height: u32) -> &mut Self {
+        let (width, height, cells) = fit_grid(width, height);
+        self.modify(|grid| {
+            grid.width = width;
+            grid.height = height;
+            grid.values = vec![0; cells];
+        });
+        return self;
+    }
+
+    // Values at the start and end of the color ramp, anything outside is clamped.
+    pub fn set_range(&mut self, min: f32, max: f32) -> &mut Self {
+        self.modify(|grid| grid.range = [min, max]);
+        return self;
+    }
+
+    pub fn set_ramp(&mut self, ramp: ColorRamp2D) -> &mut Self {
+        self.modify(|grid| grid.ramp = ramp);
+        return self;
+    }
+
+    // Draws lines between the cells in the given color, None turns them off.
+    pub fn set_wireframe(&mut self, color: Option<Color>) -> &mut Self {
+        self.modify(|grid| grid.wireframe = color);
+        return self;
+    }
+
+    pub fn get_grid(&self) -> &HeatMapGrid2D {
+        return &self.grid;
+    }
+
+    // Only copies the grid when last frame's node still holds on to it.
+    fn modify(&mut self, f: impl FnOnce(&mut HeatMapGrid2D)) {
+        let grid = Rc::make_mut(&mut self.grid);
+        f(grid);
+        grid.version += 1;
+        self.state.set_dirty(true);
+    }
+}
+
+impl Drawable2D for HeatMap2D {
+    fn get_state(&mut self) -> &mut DrawableState2D {
+        return &mut self.state;
+    }
+
+    fn draw(&mut self) -> Option<Vec<DrawNode2D>> {
+        let node = DrawNode2D::HeatMap(HeatMapDrawNode2D {
+            info: NodeInfo2D::new(self.state.get_id()),
+            transform: *self.state.get_transform_state(),
+            grid: self.grid.clone(),
+        });
+        return Some(vec![node]);
+    }
+}
+
+// Clamps the grid to what a texture can hold and returns it with its number of cells.
+fn fit_grid(width: u32, height: u32) -> (u32, u32, usize) {
+    let (fitted_width, fitted_height) = (width.min(MAX_HEAT_MAP_SIZE), height.min(MAX_HEAT_MAP_SIZE));
+    if (fitted_width, fitted_height) != (width, height) {
+        log::warn!("A {}x{} heat map doesn't fit in a texture, it's clamped to {}x{}", width, height, fitted_width, fitted_height);
+    }
+    return match (fitted_width as usize).checked_mul(fitted_height as usize) {
+        Some(cells) => (fitted_width, fitted_height, cells),
+        None => {
+            log::warn!("A {}x{} heat map has more cells than fit in memory, it's left empty", width, height);
+            (0, 0, 0)
+        }
+    };
+}
+
+// A heat map found while building a frame, with its corners already in pixels.
+pub struct HeatMapJob2D {
+    pub id: DrawableId,
+    pub grid: Rc<HeatMapGrid2D>,
+    pub corners: [Vector2<f32>; 4], // Top left, top right, bottom right, bottom left
+    pub view: usize, // Which of Pipeline2D's views it's projected with, 0 is the screen
+    pub blend_mode: BlendMode,
+    pub sample_count: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct HeatMapVertex {
+    position: [f32; 4],
+    tex_coord: [f32; 2],
+}
+
+impl HeatMapVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2];
+
+    fn new(position: Vector2<f32>, tex_coord: [f32; 2]) -> HeatMapVertex {
+        return HeatMapVertex {
+            position: [position.x, position.y, 0.0, 1.0],
+            tex_coord,
+        }
+    }
+
+    fn describe() -> wgpu::VertexBufferLayout<'static> {
+        return wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        };
+    }
+}
+
+// Matches HeatMapUniform in mesh.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct HeatMapUniform2D {
+    transform: [[f32; 4]; 4],
+    range: [f32; 2],
+    stop_count: u32,
+    padding: u32,
+    wire_color: [f32; 4],
+    stop_colors: [[f32; 4]; MAX_RAMP_STOPS],
+    stop_positions: [[f32; 4]; MAX_RAMP_STOPS / 4],
+}
+
+// Pipelines differ by blend mode, sample count and whether they draw the wireframe.
+type HeatMapPipelineKey2D = (BlendMode, u32, bool);
+
+struct HeatMapResources2D {
+    texture: Texture,
+    size: (u32, u32),
+    version: u64,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    vertex_buffer: Buffer,
+    wire_buffer: Option<Buffer>,
+    wire_count: u32,
+    key: HeatMapPipelineKey2D,
+}
+
+// The GPU side of every HeatMap2D drawn by a Pipeline2D, kept per drawable.
+pub struct HeatMapRenderer2D {
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<HeatMapPipelineKey2D, RenderPipeline>,
+    resources: HashMap<DrawableId, HeatMapResources2D>,
+}
+
+impl HeatMapRenderer2D {
+    pub fn new(r_state: &WgpuState) -> HeatMapRenderer2D {
+        let bind_group_layout = r_state.gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Heat Map Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = r_state.gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Heat Map Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        return HeatMapRenderer2D {
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+            resources: HashMap::new(),
+        }
+    }
+
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+
+    // Drops the resources of heat maps that aren't drawn anymore.
+    pub fn retain(&mut self, jobs: &[&HeatMapJob2D]) {
+        self.resources.retain(|id, _| jobs.iter().any(|job| job.id == *id));
+    }
+
+    // Uploads everything the heat map needs this frame, the matrix takes pixels to clip space.
+    pub fn prepare(&mut self, r_state: &WgpuState, job: &HeatMapJob2D, matrix: Matrix4<f32>) {
+        let grid = &job.grid;
+        let key = (job.blend_mode, job.sample_count, false);
+        for key in [key, (key.0, key.1, true)] {
+            if (!key.2 || grid.wireframe.is_some()) && !self.pipelines.contains_key(&key) {
+                let pipeline = self.create_pipeline(r_state, key);
+                self.pipelines.insert(key, pipeline);
+            }
+        }
+
+        let needs_texture = match self.resources.get(&job.id) {
+            Some(resources) => resources.size != (grid.width, grid.height),
+            None => true,
+        };
+        if needs_texture {
+            let resources = self.create_resources(r_state, grid.width, grid.height);
+            self.resources.insert(job.id, resources);
+        }
+        let resources = self.resources.get_mut(&job.id).expect("");
+        resources.key = key;
+        if needs_texture || resources.version != grid.version {
+            r_state.gpu.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &resources.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&grid.values),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(grid.width * 4),
+                    rows_per_image: Some(grid.height),
+                },
+                wgpu::Extent3d {
+                    width: grid.width,
+                    height: grid.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+            resources.version = grid.version;
+        }
+
+        let mut uniform = HeatMapUniform2D {
+            transform: matrix.into(),
+            range: grid.range,
+            stop_count: grid.ramp.get_stops().len() as u32,
+            padding: 0,
+            wire_color: grid.wireframe.unwrap_or(Color::new(0, 0, 0, 0)).to_linear_array(),
+            stop_colors: [[0.0; 4]; MAX_RAMP_STOPS],
+            stop_positions: [[0.0; 4]; MAX_RAMP_STOPS / 4],
+        };
+        for (i, (position, color)) in grid.ramp.get_stops().iter().enumerate() {
+            uniform.stop_colors[i] = color.to_linear_array();
+            uniform.stop_positions[i / 4][i % 4] = *position;
+        }
+        r_state.gpu.queue.write_buffer(&resources.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let [top_left, top_right, bottom_right, bottom_left] = job.corners;
+        let vertices = [
+            HeatMapVertex::new(top_left, [0.0, 0.0]),
+            HeatMapVertex::new(top_right, [1.0, 0.0]),
+            HeatMapVertex::new(bottom_right, [1.0, 1.0]),
+            HeatMapVertex::new(bottom_right, [1.0, 1.0]),
+            HeatMapVertex::new(bottom_left, [0.0, 1.0]),
+            HeatMapVertex::new(top_left, [0.0, 0.0]),
+        ];
+        r_state.gpu.queue.write_buffer(&resources.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        resources.wire_count = 0;
+        if grid.wireframe.is_some() {
+            // One line for every column and row edge, the corners are an affine quad so lerping them is exact.
+            let mut lines: Vec<HeatMapVertex> = vec![];
+            for i in 0..=grid.width {
+                let t = i as f32 / grid.width as f32;
+                lines.push(HeatMapVertex::new(top_left + (top_right - top_left) * t, [t, 0.0]));
+                lines.push(HeatMapVertex::new(bottom_left + (bottom_right - bottom_left) * t, [t, 1.0]));
+            }
+            for i in 0..=grid.height {
+                let t = i as f32 / grid.height as f32;
+                lines.push(HeatMapVertex::new(top_left + (bottom_left - top_left) * t, [0.0, t]));
+                lines.push(HeatMapVertex::new(top_right + (bottom_right - top_right) * t, [1.0, t]));
+            }
+            let bytes: &[u8] = bytemuck::cast_slice(&lines);
+            if resources.wire_buffer.as_ref().map(|buffer| buffer.size() < bytes.len() as u64).unwrap_or(true) {
+                resources.wire_buffer = Some(r_state.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Heat Map Wireframe"),
+                    size: (bytes.len() as u64).next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            r_state.gpu.queue.write_buffer(resources.wire_buffer.as_ref().expect(""), 0, bytes);
+            resources.wire_count = lines.len() as u32;
+        }
+    }
+
+    // Returns the number of draw calls.
+    pub fn draw<'r>(&'r self, rpass: &mut RenderPass<'r>, id: DrawableId) -> u32 {
+        let resources = match self.resources.get(&id) {
+            Some(resources) => resources,
+            None => return 0,
+        };
+        rpass.set_pipeline(&self.pipelines[&resources.key]);
+        rpass.set_bind_group(0, &resources.bind_group, &[]);
+        rpass.set_vertex_buffer(0, resources.vertex_buffer.slice(..));
+        rpass.draw(0..6, 0..1);
+        if let (Some(wire_buffer), true) = (&resources.wire_buffer, resources.wire_count > 0) {
+            rpass.set_pipeline(&self.pipelines[&(resources.key.0, resources.key.1, true)]);
+            rpass.set_vertex_buffer(0, wire_buffer.slice(..));
+            rpass.draw(0..resources.wire_count, 0..1);
+            return 2;
+        }
+        return 1;
+    }
+
+    fn create_resources(&self, r_state: &WgpuState, width: u32, height: u32) -> HeatMapResources2D {
+        let texture = r_state.gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Heat Map Values"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let uniform_buffer = r_state.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Heat Map Uniforms"),
+            size: std::mem::size_of::<HeatMapUniform2D>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = r_state.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Heat Map Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+        });
+        let vertex_buffer = r_state.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Heat Map Vertices"),
+            size: (std::mem::size_of::<HeatMapVertex>() * 6) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        return HeatMapResources2D {
+            texture,
+            size: (width, height),
+            version: 0,
+            uniform_buffer,
+            bind_group,
+            vertex_buffer,
+            wire_buffer: None,
+            wire_count: 0,
+            key: (BlendMode::Normal, 1, false),
+        };
+    }
+
+    fn create_pipeline(&self, r_state: &WgpuState, key: HeatMapPipelineKey2D) -> RenderPipeline {
+        let (blend_mode, sample_count, wire) = key;
+        return r_state.gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heat Map Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &r_state.gpu.shader_modules.borrow().mesh_shader,
+                entry_point: "vs_main",
+                buffers: &[HeatMapVertex::describe()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &r_state.gpu.shader_modules.borrow().mesh_shader,
+                entry_point: if wire { "fs_wire" } else { "fs_main" },
+                targets: &[Some(wgpu::ColorTargetState {
+                    blend: Some(blend_mode.to_blend_state()),
+                    ..r_state.color_target.clone()
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: if wire { wgpu::PrimitiveTopology::LineList } else { wgpu::PrimitiveTopology::TriangleList },
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+    }
+}
+
+// The corners of the node's rectangle after its affine transforms.
+pub fn heat_map_corners(node: &HeatMapDrawNode2D) -> [Vector2<f32>; 4] {
+    let matrix = node.info.get_matrix();
+    let (pos, size) = (node.transform.abs_pos, node.transform.abs_size);
+    let corner = |x: f32, y: f32| {
+        let corner = matrix * Vector3::new(pos.x + x * size.x, pos.y + y * size.y, 1.0);
+        return Vector2::new(corner.x, corner.y);
+    };
+    return [corner(0.0, 0.0), corner(1.0, 0.0), corner(1.0, 1.0), corner(0.0, 1.0)];
+}
\ No newline at end of file
@@ -0,0 +1,59 @@
+use crate::{math::color::Color, renderer::texture_atlas::AtlasImageId};
+
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, ImageDrawNode2D, NodeInfo2D}};
+
+// Draws an image that lives in the Pipeline2D texture atlas, the color is used as a tint.
+pub struct Image2D {
+    state: DrawableState2D,
+    image: AtlasImageId,
+    color: Color,
+    corner_radius: f32,
+}
+
+impl Image2D {
+    pub fn new(image: AtlasImageId) -> Image2D {
+        return Image2D {
+            state: DrawableState2D::new(),
+            image,
+            color: Color::new(255, 255, 255, 255),
+            corner_radius: 0.0,
+        }
+    }
+
+    pub fn set_image(&mut self, image: AtlasImageId) -> &mut Self {
+        self.image = image;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    pub fn set_color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    // Rounds all four corners, in pixels. 0 keeps them square.
+    pub fn set_corner_radius(&mut self, radius: f32) -> &mut Self {
+        self.corner_radius = radius.max(0.0);
+        self.state.set_dirty(true);
+        return self;
+    }
+}
+
+impl Drawable2D for Image2D {
+    fn get_state(&mut self) -> &mut DrawableState2D {
+        return &mut self.state;
+    }
+
+    fn draw(&mut self) -> Option<Vec<DrawNode2D>> {
+        let node = DrawNode2D::Image(ImageDrawNode2D {
+            info: NodeInfo2D::new(self.state.get_id()),
+            transform: *self.state.get_transform_state(),
+            image: self.image,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: self.color.to_linear_vec4(),
+            corner_radius: self.corner_radius,
+        });
+        return Some(vec![node]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use std::sync::atomic::AtomicU64;
+
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, ShaderModule};
+
+use crate::renderer::WgpuState;
+
+static MATERIAL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Appended after ui.wgsl and the material's own source. Materials provide
+// `fn material(vertex: UIVertexOutput, color: vec4<f32>) -> vec4<f32>`, color being the texture sample times the vertex color,
+// and can declare `@group(2) @binding(0) var<uniform>` for their uniform block. Other shader files can be pulled in with #include.
+const MATERIAL_ENTRY: &str = "
+@fragment
+fn fragment_material(vertex: UIVertexOutput) -> @location(0) vec4<f32> {
+    let sampled = textureSample(atlas_texture, atlas_sampler, vertex.uv) * vertex.color;
+    let color = material(vertex, sampled);
+    let alpha = color.a * quad_coverage(vertex);
+    return vec4(color.rgb * alpha, alpha);
+}";
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MaterialId(u64);
+
+// A user fragment shader and its uniform block, registered with Pipeline2D and attached to drawables by id.
+pub struct Material2D {
+    source: String,
+    uniform_buffer: Buffer,
+    pub bind_group: BindGroup,
+    version: u64, // Bumped on every uniform write so cached layers know to redraw
+}
+
+impl Material2D {
+    pub fn new(r_state: &WgpuState, layout: &BindGroupLayout, source: &str, uniform_size: u64) -> (MaterialId, Material2D) {
+        let (uniform_buffer, bind_group) = Self::create_uniforms(&r_state.gpu.device, layout, uniform_size);
+        let id = MaterialId(MATERIAL_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst));
+        return (id, Material2D {
+            source: source.to_string(),
+            uniform_buffer,
+            bind_group,
+            version: 0,
+        });
+    }
+
+    // Pipeline2D builds a module for every ui.wgsl variant the material is drawn with.
+    pub fn create_module(&self, r_state: &WgpuState, defines: &[(&str, &str)]) -> Result<ShaderModule, String> {
+        let wgsl = format!("#include \"ui.wgsl\"\n{}\n{}", self.source, MATERIAL_ENTRY);
+        return r_state.gpu.shader_modules.borrow().create_variant(&r_state.gpu.device, &wgsl, "2D Material", defines);
+    }
+
+    // Uniform buffers can't be empty, and their size has to be a multiple of 16.
+    fn create_uniforms(device: &Device, layout: &BindGroupLayout, size: u64) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("2D Material Uniforms"),
+            size: size.max(16).div_ceil(16) * 16,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("2D Material Uniforms"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        return (buffer, bind_group);
+    }
+
+    pub fn write_uniforms(&mut self, r_state: &WgpuState, data: &[u8]) {
+        if data.len() as u64 > self.uniform_buffer.size() {
+            log::warn!("Material uniforms are {} bytes but the block is only {}", data.len(), self.uniform_buffer.size());
+            return;
+        }
+        // Buffer writes have to be a multiple of 4 bytes.
+        let mut padded = data.to_vec();
+        padded.resize(data.len().div_ceil(4) * 4, 0);
+        r_state.gpu.queue.write_buffer(&self.uniform_buffer, 0, &padded);
+        self.version += 1;
+    }
+
+    pub fn get_version(&self) -> u64 {
+        return self.version;
+    }
+}
\ No newline at end of file
@@ -2,4 +2,13 @@ pub mod drawable_2d;
 pub mod pipeline_2d;
 pub mod drawable_state_2d;
 pub mod box_2d;
-pub mod text_2d;
\ No newline at end of file
+pub mod text_2d;
+pub mod image_2d;
+pub mod nine_slice_2d;
+pub mod sprite_2d;
+pub mod camera_2d;
+pub mod path_2d;
+pub mod post_process_2d;
+pub mod material_2d;
+pub mod heat_map_2d;
+pub mod render_stats_2d;
\ No newline at end of file
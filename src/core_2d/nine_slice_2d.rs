@@ -0,0 +1,217 @@
+use crate::{math::color::Color, renderer::texture_atlas::{AtlasImageId, TextureAtlas}};
+
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, ImageDrawNode2D, NodeInfo2D, TransformState2D, Anchor}};
+
+// One column or row of the 9-patch, positions and sizes are split the same way TransformState2D does it.
+struct SliceSpan {
+    abs_pos: f32,
+    rel_pos: f32,
+    abs_size: f32,
+    rel_size: f32,
+    uv_pos: f32,
+    uv_size: f32,
+}
+
+// Draws an atlas image as a 9-patch: the corners keep their pixel size,
+// the edges stretch along one axis and the centre stretches along both.
+pub struct NineSlice2D {
+    state: DrawableState2D,
+    image: AtlasImageId,
+    image_size: (f32, f32), // Nothing is drawn while it's empty
+    // Insets in pixels of the source image, in left, top, right, bottom order
+    margins: [f32; 4],
+    color: Color,
+}
+
+impl NineSlice2D {
+    pub fn new(atlas: &TextureAtlas, image: AtlasImageId) -> NineSlice2D {
+        return NineSlice2D {
+            state: DrawableState2D::new(),
+            image,
+            image_size: Self::image_size(atlas, image),
+            margins: [0.0, 0.0, 0.0, 0.0],
+            color: Color::new(255, 255, 255, 255),
+        }
+    }
+
+    pub fn set_image(&mut self, atlas: &TextureAtlas, image: AtlasImageId) -> &mut Self {
+        self.image = image;
+        self.image_size = Self::image_size(atlas, image);
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    // Margins that don't fit in the image or in the drawn size are scaled down, negative ones count as 0.
+    pub fn set_margins(&mut self, left: f32, top: f32, right: f32, bottom: f32) -> &mut Self {
+        self.margins = [left, top, right, bottom];
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    pub fn set_color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    fn image_size(atlas: &TextureAtlas, image: AtlasImageId) -> (f32, f32) {
+        return match atlas.get_image_size(image) {
+            Some((width, height)) => (width as f32, height as f32),
+            None => {
+                log::warn!("NineSlice2D was given an image that isn't in the atlas");
+                (0.0, 0.0)
+            }
+        };
+    }
+
+    // Scales the margins down together until they fit in the size, so the edges meet and the centre disappears.
+    fn fit_margins(start: f32, end: f32, size: f32) -> (f32, f32) {
+        let (start, end) = (start.max(0.0), end.max(0.0));
+        if start + end <= size {
+            return (start, end);
+        }
+        let scale = size.max(0.0) / (start + end);
+        return (start * scale, end * scale);
+    }
+
+    // The image size can't be 0, draw skips empty images.
+    fn spans(start: f32, end: f32, abs_size: f32, rel_size: f32, image_size: f32) -> [SliceSpan; 3] {
+        let (start, end) = Self::fit_margins(start, end, image_size);
+        // A size relative to the parent is only known once the tree is laid out, so only absolute sizes fit the margins.
+        let (fit_start, fit_end) = if rel_size == 0.0 { Self::fit_margins(start, end, abs_size) } else { (start, end) };
+        return [
+            SliceSpan {
+                abs_pos: 0.0,
+                rel_pos: 0.0,
+                abs_size: fit_start,
+                rel_size: 0.0,
+                uv_pos: 0.0,
+                uv_size: start / image_size,
+            },
+            SliceSpan {
+                abs_pos: fit_start,
+                rel_pos: 0.0,
+                abs_size: if rel_size == 0.0 { (abs_size - fit_start - fit_end).max(0.0) } else { abs_size - fit_start - fit_end },
+                rel_size,
+                uv_pos: start / image_size,
+                uv_size: (image_size - start - end) / image_size,
+            },
+            SliceSpan {
+                abs_pos: abs_size - fit_end,
+                rel_pos: rel_size,
+                abs_size: fit_end,
+                rel_size: 0.0,
+                uv_pos: (image_size - end) / image_size,
+                uv_size: end / image_size,
+            },
+        ];
+    }
+}
+
+impl Drawable2D for NineSlice2D {
+    fn get_state(&mut self) -> &mut DrawableState2D {
+        return &mut self.state;
+    }
+
+    fn draw(&mut self) -> Option<Vec<DrawNode2D>> {
+        if self.image_size.0 <= 0.0 || self.image_size.1 <= 0.0 {
+            return Some(vec![]);
+        }
+        // The slices all have different sizes, so the alignment has to be resolved against the whole patch first.
+        let mut base: TransformState2D = *self.state.get_transform_state();
+        base.apply_alignment();
+        base.alignment = Anchor::TOP_LEFT;
+
+        let [left, top, right, bottom] = self.margins;
+        let columns = Self::spans(left, right, base.abs_size.x, base.rel_size.x, self.image_size.0);
+        let rows = Self::spans(top, bottom, base.abs_size.y, base.rel_size.y, self.image_size.1);
+        let color = self.color.to_linear_vec4();
+        let id = self.state.get_id();
+
+        let mut nodes: Vec<DrawNode2D> = Vec::with_capacity(9);
+        for row in &rows {
+            for column in &columns {
+                let mut transform = base;
+                transform.abs_pos.x += column.abs_pos;
+                transform.abs_pos.y += row.abs_pos;
+                transform.rel_pos.x += column.rel_pos;
+                transform.rel_pos.y += row.rel_pos;
+                transform.abs_size.x = column.abs_size;
+                transform.abs_size.y = row.abs_size;
+                transform.rel_size.x = column.rel_size;
+                transform.rel_size.y = row.rel_size;
+                nodes.push(DrawNode2D::Image(ImageDrawNode2D {
+                    info: NodeInfo2D::new(id),
+                    transform,
+                    image: self.image,
+                    uv_rect: [column.uv_pos, row.uv_pos, column.uv_size, row.uv_size],
+                    color,
+                    corner_radius: 0.0,
+                }));
+            }
+        }
+        return Some(nodes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_spans(spans: &[SliceSpan; 3], abs_pos: [f32; 3], abs_size: [f32; 3], uv_pos: [f32; 3], uv_size: [f32; 3]) {
+        for (i, span) in spans.iter().enumerate() {
+            assert!((span.abs_pos - abs_pos[i]).abs() < 1e-5, "span {} starts at {}, not {}", i, span.abs_pos, abs_pos[i]);
+            assert!((span.abs_size - abs_size[i]).abs() < 1e-5, "span {} is {} long, not {}", i, span.abs_size, abs_size[i]);
+            assert!((span.uv_pos - uv_pos[i]).abs() < 1e-5, "span {} samples from {}, not {}", i, span.uv_pos, uv_pos[i]);
+            assert!((span.uv_size - uv_size[i]).abs() < 1e-5, "span {} samples {}, not {}", i, span.uv_size, uv_size[i]);
+        }
+    }
+
+    #[test]
+    fn corners_keep_their_size_and_the_centre_stretches() {
+        let spans = NineSlice2D::spans(10.0, 5.0, 100.0, 0.0, 40.0);
+        assert_spans(&spans, [0.0, 10.0, 95.0], [10.0, 85.0, 5.0], [0.0, 0.25, 0.875], [0.25, 0.625, 0.125]);
+    }
+
+    #[test]
+    fn margins_wider_than_the_drawn_size_meet_in_the_middle() {
+        let spans = NineSlice2D::spans(30.0, 10.0, 20.0, 0.0, 40.0);
+        // The whole image is still sampled, squeezed into the smaller corners.
+        assert_spans(&spans, [0.0, 15.0, 15.0], [15.0, 0.0, 5.0], [0.0, 0.75, 0.75], [0.75, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn margins_wider_than_the_image_are_scaled_to_it() {
+        let spans = NineSlice2D::spans(30.0, 30.0, 100.0, 0.0, 40.0);
+        assert_spans(&spans, [0.0, 20.0, 80.0], [20.0, 60.0, 20.0], [0.0, 0.5, 0.5], [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn negative_margins_count_as_zero() {
+        let spans = NineSlice2D::spans(-10.0, 10.0, 100.0, 0.0, 40.0);
+        assert_spans(&spans, [0.0, 0.0, 90.0], [0.0, 90.0, 10.0], [0.0, 0.0, 0.75], [0.0, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn relative_sizes_stretch_only_the_centre() {
+        let spans = NineSlice2D::spans(10.0, 10.0, 0.0, 1.0, 40.0);
+        assert_spans(&spans, [0.0, 10.0, -10.0], [10.0, -20.0, 10.0], [0.0, 0.25, 0.75], [0.25, 0.5, 0.25]);
+        assert_eq!([spans[0].rel_size, spans[1].rel_size, spans[2].rel_size], [0.0, 1.0, 0.0]);
+        assert_eq!([spans[0].rel_pos, spans[1].rel_pos, spans[2].rel_pos], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn an_empty_image_draws_nothing() {
+        let mut nine_slice = NineSlice2D {
+            state: DrawableState2D::new(),
+            image: AtlasImageId::dummy(),
+            image_size: (0.0, 0.0),
+            margins: [4.0; 4],
+            color: Color::new(255, 255, 255, 255),
+        };
+        nine_slice.set_abs_size(100.0, 100.0);
+        assert!(nine_slice.draw().unwrap().is_empty());
+        nine_slice.image_size = (16.0, 16.0);
+        assert_eq!(nine_slice.draw().unwrap().len(), 9);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::{f32::consts::PI, rc::Rc};
+
+use cgmath::Vector2;
+
+use crate::math::{color::Color, tessellation::{self, PathCommand2D, StrokeOptions, Mesh2D, DEFAULT_TOLERANCE}};
+
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, MeshDrawNode2D, NodeInfo2D}};
+
+// Vector shapes made of lines, curves and arcs, tessellated into triangles on the CPU.
+// Path coordinates are in pixels relative to the drawable's position.
+// The triangles have no analytic anti-aliasing like quads do, their edges are only smooth with MSAA.
+pub struct Path2D {
+    state: DrawableState2D,
+    commands: Vec<PathCommand2D>,
+    fill_color: Option<Color>,
+    stroke_color: Option<Color>,
+    stroke_options: StrokeOptions,
+    tolerance: f32,
+    // Tessellated meshes are kept until the path or its style changes
+    fill_mesh: Option<Rc<Mesh2D>>,
+    stroke_mesh: Option<Rc<Mesh2D>>,
+}
+
+impl Path2D {
+    pub fn new() -> Path2D {
+        return Path2D {
+            state: DrawableState2D::new(),
+            commands: vec![],
+            fill_color: None,
+            stroke_color: Some(Color::new(255, 255, 255, 255)),
+            stroke_options: StrokeOptions::new(1.0),
+            tolerance: DEFAULT_TOLERANCE,
+            fill_mesh: None,
+            stroke_mesh: None,
+        }
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        return self.push(PathCommand2D::MoveTo(Vector2::new(x, y)));
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        return self.push(PathCommand2D::LineTo(Vector2::new(x, y)));
+    }
+
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) -> &mut Self {
+        return self.push(PathCommand2D::QuadTo(Vector2::new(cx, cy), Vector2::new(x, y)));
+    }
+
+    pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) -> &mut Self {
+        return self.push(PathCommand2D::CubicTo(Vector2::new(c1x, c1y), Vector2::new(c2x, c2y), Vector2::new(x, y)));
+    }
+
+    // Continues the current contour with a line to the start of the arc. Angles are in radians.
+    pub fn arc(&mut self, cx: f32, cy: f32, radius: f32, start_angle: f32, end_angle: f32) -> &mut Self {
+        return self.push(PathCommand2D::Arc {
+            center: Vector2::new(cx, cy),
+            radius,
+            start_angle,
+            end_angle,
+        });
+    }
+
+    pub fn close(&mut self) -> &mut Self {
+        return self.push(PathCommand2D::Close);
+    }
+
+    pub fn add_polyline(&mut self, points: &[(f32, f32)]) -> &mut Self {
+        for (i, (x, y)) in points.iter().enumerate() {
+            if i == 0 {
+                self.move_to(*x, *y);
+            } else {
+                self.line_to(*x, *y);
+            }
+        }
+        return self;
+    }
+
+    pub fn add_polygon(&mut self, points: &[(f32, f32)]) -> &mut Self {
+        return self.add_polyline(points).close();
+    }
+
+    pub fn add_circle(&mut self, cx: f32, cy: f32, radius: f32) -> &mut Self {
+        self.move_to(cx + radius, cy);
+        return self.arc(cx, cy, radius, 0.0, 2.0 * PI).close();
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.commands.clear();
+        self.invalidate();
+        return self;
+    }
+
+    pub fn set_fill_color(&mut self, color: Option<Color>) -> &mut Self {
+        self.fill_color = color;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    // A translucent stroke is blended twice where the path crosses itself, or on the inside of a turn that's sharper than the stroke is wide.
+    pub fn set_stroke_color(&mut self, color: Option<Color>) -> &mut Self {
+        self.stroke_color = color;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    pub fn set_stroke_options(&mut self, options: StrokeOptions) -> &mut Self {
+        self.stroke_options = options;
+        self.stroke_mesh = None;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) -> &mut Self {
+        self.tolerance = tolerance;
+        self.invalidate();
+        return self;
+    }
+
+    fn push(&mut self, command: PathCommand2D) -> &mut Self {
+        self.commands.push(command);
+        self.invalidate();
+        return self;
+    }
+
+    fn invalidate(&mut self) {
+        self.fill_mesh = None;
+        self.stroke_mesh = None;
+        self.state.set_dirty(true);
+    }
+}
+
+impl Drawable2D for Path2D {
+    fn get_state(&mut self) -> &mut DrawableState2D {
+        return &mut self.state;
+    }
+
+    fn draw(&mut self) -> Option<Vec<DrawNode2D>> {
+        let mut nodes: Vec<DrawNode2D> = vec![];
+        let needs_fill = self.fill_color.is_some() && self.fill_mesh.is_none();
+        let needs_stroke = self.stroke_color.is_some() && self.stroke_mesh.is_none();
+        if needs_fill || needs_stroke {
+            let contours = tessellation::flatten(&self.commands, self.tolerance);
+            if needs_fill {
+                self.fill_mesh = Some(Rc::new(tessellation::fill(&contours)));
+            }
+            if needs_stroke {
+                self.stroke_mesh = Some(Rc::new(tessellation::stroke(&contours, &self.stroke_options)));
+            }
+        }
+
+        let transform = *self.state.get_transform_state();
+        let id = self.state.get_id();
+        // The fill goes first so the stroke is drawn over its edge.
+        if let (Some(color), Some(mesh)) = (self.fill_color, &self.fill_mesh) {
+            nodes.push(DrawNode2D::Mesh(MeshDrawNode2D {
+                info: NodeInfo2D::new(id),
+                transform,
+                mesh: mesh.clone(),
+                color: color.to_linear_vec4(),
+            }));
+        }
+        if let (Some(color), Some(mesh)) = (self.stroke_color, &self.stroke_mesh) {
+            nodes.push(DrawNode2D::Mesh(MeshDrawNode2D {
+                info: NodeInfo2D::new(id),
+                transform,
+                mesh: mesh.clone(),
+                color: color.to_linear_vec4(),
+            }));
+        }
+        return Some(nodes);
+    }
+}
\ No newline at end of file
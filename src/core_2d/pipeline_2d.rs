@@ -1,223 +1,1136 @@
-use std::{sync::Arc, rc::Rc, cell::RefCell, marker::PhantomData, collections::HashMap};
+use std::{rc::Rc, cell::Cell, collections::{HashMap, hash_map::DefaultHasher}, ops::Range, hash::{Hash, Hasher}, time::Instant};
 
 use bytemuck::{Pod, Zeroable};
-use wgpu::{RenderPipeline, BindGroup, Device, RenderPass, ColorTargetState, BindGroupLayout, Buffer, util::{BufferInitDescriptor, DeviceExt, StagingBelt}, BufferUsages, TextureFormat, MultisampleState, CommandEncoder, TextureView, BufferSlice};
-use wgpu_glyph::{ab_glyph::FontArc, GlyphBrushBuilder, GlyphBrush, Section, Text};
+use wgpu::{PipelineLayout, RenderPipeline, BindGroup, Device, RenderPass, BindGroupLayout, Buffer, util::StagingBelt, BufferUsages, TextureFormat, CommandEncoder, TextureView, Sampler, ShaderModule};
+use wgpu_glyph::{GlyphBrushBuilder, GlyphBrush, Section, Text};
 
-use crate::renderer::{ShaderModules, WgpuState};
+use cgmath::{Vector2, Vector3, Matrix4};
 
-use super::{drawable_2d::{Drawable2D, SimpleDrawable2D}, drawable_state_2d::{DrawNode2D, DrawableId, QuadDrawNode2D}};
+use crate::renderer::{WgpuState, gpu_context::GpuContext, texture_atlas::TextureAtlas, blend_mode::BlendMode};
 
-// Info used by the renderer to render the drawable
-struct DrawableRenderInfo {
-    nodes: Vec<DrawNode2D>,
-    buffers: Vec<Buffer>
-}
+use super::{camera_2d::Camera2D, heat_map_2d::{HeatMapRenderer2D, HeatMapJob2D, heat_map_corners}, material_2d::{Material2D, MaterialId}, post_process_2d::{PostProcessor2D, PostEffect2D, SCENE_TARGET, BACKDROP_TARGET}, render_stats_2d::{RenderStats2D, GpuTimer2D}, drawable_2d::{Drawable2D, SimpleDrawable2D, apply_drawable_state}, drawable_state_2d::{DrawNode2D, DrawableId, TransformState2D, NodeInfo2D, MeshDrawNode2D, flatten_nodes}};
+
+// Edges of quads are faded over this many pixels outside their rectangle when MSAA is off.
+const AA_MARGIN: f32 = 1.0;
+// Index of the plain screen projection in Pipeline2D::views, every root's camera view comes after it.
+const SCREEN_VIEW: usize = 0;
 
 pub struct Pipeline2D {
-    root_id: DrawableId, // Used for API checking.
-    pub pipeline_2d: Box<RenderPipeline>,
-    quad_index_buffer: Buffer,
-    current_buffer: Buffer,
+    roots: Vec<Root2D>, // Sorted by order, the lowest is drawn first
+    pipelines: HashMap<PipelineKey2D, RenderPipeline>, // Built the first time a combination is drawn
+    pipeline_layout: PipelineLayout,
+    material_layout: BindGroupLayout, // Group 2, the material's uniform block
+    material_pipeline_layout: PipelineLayout,
+    materials: HashMap<MaterialId, Material2D>,
+    shader_variants: HashMap<ShaderVariant2D, Option<ShaderModule>>, // None when the variant failed to compile
+    format: TextureFormat,
+    shader_generation: u64, // Pipelines are rebuilt when the shader modules were reloaded
+    sample_count: u32,
+    multisample_target: Option<MultisampleTarget2D>,
+    layer_targets: HashMap<DrawableId, LayerTarget2D>,
+    layer_sampler: Sampler,
+    gpu: Rc<GpuContext>, // The texture atlas is shared with the pipelines of other windows through it
+    pub camera: Camera2D,
+    pub post_effects: Vec<PostEffect2D>,
+    post: PostProcessor2D,
+    heat_maps: HeatMapRenderer2D,
+    stats: RenderStats2D,
+    gpu_timer: Option<GpuTimer2D>, // None when the adapter can't do timestamp queries
+    stats_overlay: bool,
+    view_layout: BindGroupLayout,
+    views: Vec<ViewUniform2D>, // The screen, then one for each root in drawing order
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
     debug_glyph_brush: GlyphBrush<()>,
     debug_glyph_staging_belt: StagingBelt,
+    warned_aliased_meshes: Cell<bool>, // Set once a path was drawn without MSAA, so that's only logged once
+}
+
+// Where a batch samples its texture from.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum TextureSource2D {
+    Atlas(usize),
+    Layer(DrawableId),
+    Backdrop(usize), // Index into DrawList2D::backdrops
+    HeatMap(DrawableId), // Drawn by HeatMapRenderer2D, the batch has no indices
+}
+
+// Everything that needs a RenderPipeline of its own.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct PipelineKey2D {
+    blend_mode: BlendMode,
+    layer_composite: bool, // Layer textures are already premultiplied
+    material: Option<MaterialId>,
+    rounded_corners: bool,
+    sample_count: u32,
+}
+
+// A version of ui.wgsl built with its own defines, the plain one is ShaderModules::ui_shader.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct ShaderVariant2D {
+    material: Option<MaterialId>,
+    rounded_corners: bool,
+}
+
+impl ShaderVariant2D {
+    fn new(key: &PipelineKey2D) -> ShaderVariant2D {
+        return ShaderVariant2D {
+            material: key.material,
+            rounded_corners: key.rounded_corners,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        return self.material.is_none() && !self.rounded_corners;
+    }
+
+    fn defines(&self) -> Vec<(&'static str, &'static str)> {
+        let mut defines = vec![];
+        if self.rounded_corners {
+            defines.push(("ROUNDED_CORNERS", ""));
+        }
+        return defines;
+    }
+}
+
+// A tree registered with Pipeline2D, drawn into the same frame as the others.
+struct Root2D {
+    id: DrawableId,
+    order: i32,
+    camera: Option<Camera2D>, // Pipeline2D::camera when None
+}
+
+// A run of indices that can be drawn with the same texture, view and pipeline bound.
+struct DrawBatch2D {
+    texture: TextureSource2D,
+    view: usize,
+    pipeline: PipelineKey2D,
+    indices: Range<u32>,
+}
+
+// What a node needs bound to be drawn, nodes that don't care about the texture (None) join whatever atlas batch is open.
+#[derive(Copy, Clone)]
+struct BatchKey2D {
+    texture: Option<TextureSource2D>,
+    view: usize,
+    pipeline: PipelineKey2D,
+}
+
+// What push_quad puts inside a quad's rectangle.
+struct QuadFill2D {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    color: [f32; 4], // Linear
+    radius: f32, // Corner radius in pixels, 0 keeps the corners square
+}
+
+// Settings that stay the same for a whole render target while its geometry is built.
+#[derive(Copy, Clone)]
+struct BuildContext2D {
+    world_view: usize, // The view of the root's camera, world space nodes are drawn with it
+    camera: Camera2D,
+    world_projection: Matrix4<f32>,
+    analytic_aa: bool,
+    sample_count: u32,
+    window_size: Vector2<f32>, // Logical pixels
+    scale_factor: f32,
+    allow_backdrops: bool,
+}
+
+// What gets drawn into one render target, the frame itself or a layer.
+struct DrawList2D<'n> {
+    batches: Vec<DrawBatch2D>,
+    screen_sections: Vec<Section<'n>>,
+    world_sections: Vec<(usize, Section<'n>)>, // With the view they're drawn through
+    backdrops: Vec<f32>, // Blur radius of every backdrop, in drawing order
+    heat_maps: Vec<HeatMapJob2D>,
+}
+
+impl<'n> DrawList2D<'n> {
+    fn new() -> DrawList2D<'n> {
+        return DrawList2D {
+            batches: vec![],
+            screen_sections: vec![],
+            world_sections: vec![],
+            backdrops: vec![],
+            heat_maps: vec![],
+        }
+    }
+}
+
+struct LayerJob2D<'n> {
+    id: DrawableId,
+    cached: bool,
+    content_hash: u64,
+    list: DrawList2D<'n>,
+}
+
+// The texture MSAA renders into before it's resolved to the frame.
+struct MultisampleTarget2D {
+    view: TextureView,
+    width: u32,
+    height: u32,
+}
+
+// Offscreen texture a layer's subtree is rendered into, it covers the whole window.
+struct LayerTarget2D {
+    view: TextureView,
+    bind_group: BindGroup,
+    width: u32,
+    height: u32,
+    content_hash: Option<u64>, // What's in the texture right now, for cached layers
+}
+
+// The view-projection matrix the vertex shader moves pixels into clip space with.
+struct ViewUniform2D {
+    buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl ViewUniform2D {
+    fn new(device: &Device, layout: &BindGroupLayout, label: &str) -> ViewUniform2D {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        return ViewUniform2D {
+            buffer,
+            bind_group,
+        }
+    }
+
+    fn write(&self, r_state: &WgpuState, matrix: Matrix4<f32>) {
+        let matrix: [[f32; 4]; 4] = matrix.into();
+        r_state.gpu.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&matrix));
+    }
 }
 
 impl Pipeline2D {
     pub fn new(r_state: &WgpuState, root: &mut SimpleDrawable2D) -> Pipeline2D {
-        let pipeline_layout = r_state.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let atlas = r_state.gpu.atlas.borrow();
+        let view_layout = r_state.gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("2D View Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = r_state.gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[],
+            bind_group_layouts: &[&atlas.bind_group_layout, &view_layout],
             push_constant_ranges: &[],
         });
-    
-        let pipeline_2d = r_state.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let material_layout = r_state.gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("2D Material Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let material_pipeline_layout = r_state.gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("2D Material Pipeline Layout"),
+            bind_group_layouts: &[&atlas.bind_group_layout, &view_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let layer_sampler = r_state.gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("2D Layer Sampler"),
+            ..Default::default()
+        });
+        let post = PostProcessor2D::new(r_state, &atlas.bind_group_layout);
+
+        // Set up font rendering
+        // Prepare glyph_brush
+        let debug_glyph_brush = GlyphBrushBuilder::using_font(r_state.gpu.font.clone())
+            .build(&r_state.gpu.device, r_state.render_format);
+
+        return Pipeline2D {
+            roots: vec![Root2D { id: root.get_id(), order: 0, camera: None }],
+            pipelines: HashMap::new(),
+            pipeline_layout,
+            material_layout,
+            material_pipeline_layout,
+            materials: HashMap::new(),
+            shader_variants: HashMap::new(),
+            format: r_state.render_format,
+            shader_generation: r_state.gpu.shader_modules.borrow().get_generation(),
+            sample_count: r_state.renderer_config.get_sample_count(),
+            multisample_target: None,
+            layer_targets: HashMap::new(),
+            layer_sampler,
+            gpu: r_state.gpu.clone(),
+            camera: Camera2D::new(),
+            post_effects: vec![],
+            post,
+            heat_maps: HeatMapRenderer2D::new(r_state),
+            stats: RenderStats2D::default(),
+            gpu_timer: GpuTimer2D::new(r_state),
+            stats_overlay: false,
+            views: vec![ViewUniform2D::new(&r_state.gpu.device, &view_layout, "2D Screen View")],
+            view_layout,
+            vertex_buffer: Self::create_buffer(&r_state.gpu.device, "2D Vertex Buffer", 1024, BufferUsages::VERTEX),
+            index_buffer: Self::create_buffer(&r_state.gpu.device, "2D Index Buffer", 1024, BufferUsages::INDEX),
+            debug_glyph_brush,
+            debug_glyph_staging_belt: StagingBelt::new(1024),
+            warned_aliased_meshes: Cell::new(false),
+        };
+    }
+
+    // 1 turns MSAA off and uses analytic edge anti-aliasing in the shader instead.
+    // Counts the adapter can't render to the surface with are lowered to the highest one it can.
+    pub fn set_sample_count(&mut self, r_state: &WgpuState, sample_count: u32) {
+        let supported = r_state.supported_sample_count(sample_count);
+        if supported != sample_count.max(1) {
+            log::warn!("{} MSAA samples aren't supported, using {} instead", sample_count, supported);
+        }
+        let sample_count = supported;
+        if sample_count == self.sample_count {
+            return;
+        }
+        self.sample_count = sample_count;
+        self.multisample_target = None;
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        return self.sample_count;
+    }
+
+    pub fn get_stats(&self) -> &RenderStats2D {
+        return &self.stats;
+    }
+
+    // Draws the stats of the previous frame in the top left corner, on top of post-processing.
+    pub fn set_stats_overlay(&mut self, enabled: bool) {
+        self.stats_overlay = enabled;
+    }
+
+    // Registers another tree to draw into the same frames, like a HUD or a debug overlay. Roots with a higher order
+    // are drawn on top, the root Pipeline2D was created with has order 0. Registering a root again changes its order.
+    pub fn add_root(&mut self, root: &mut dyn Drawable2D, order: i32) {
+        let id = root.get_id();
+        let camera = match self.roots.iter().position(|entry| entry.id == id) {
+            Some(i) => self.roots.remove(i).camera,
+            None => None,
+        };
+        // Roots with the same order keep the order they were added in.
+        let i = self.roots.iter().position(|entry| entry.order > order).unwrap_or(self.roots.len());
+        self.roots.insert(i, Root2D { id, order, camera });
+    }
+
+    pub fn remove_root(&mut self, id: DrawableId) {
+        self.roots.retain(|entry| entry.id != id);
+    }
+
+    // Gives the root's world space drawables a camera of their own, None goes back to Pipeline2D::camera.
+    pub fn set_root_camera(&mut self, id: DrawableId, camera: Option<Camera2D>) {
+        if let Some(entry) = self.roots.iter_mut().find(|entry| entry.id == id) {
+            entry.camera = camera;
+        }
+    }
+
+    // The camera the root's world space drawables are seen through.
+    pub fn get_root_camera(&self, id: DrawableId) -> Camera2D {
+        return self.roots.iter().find(|entry| entry.id == id).and_then(|entry| entry.camera).unwrap_or(self.camera);
+    }
+
+    // Registers a WGSL material, see material_2d.rs for what the source has to provide. The uniform block is in bytes.
+    pub fn register_material(&mut self, r_state: &WgpuState, source: &str, uniform_size: u64) -> MaterialId {
+        let (id, material) = Material2D::new(r_state, &self.material_layout, source, uniform_size);
+        self.materials.insert(id, material);
+        return id;
+    }
+
+    pub fn set_material_uniforms(&mut self, r_state: &WgpuState, id: MaterialId, data: &[u8]) {
+        if let Some(material) = self.materials.get_mut(&id) {
+            material.write_uniforms(r_state, data);
+        }
+    }
+
+    // Drawables still using the material are drawn without it.
+    pub fn remove_material(&mut self, id: MaterialId) {
+        self.materials.remove(&id);
+        self.pipelines.retain(|key, _| key.material != Some(id));
+        self.shader_variants.retain(|variant, _| variant.material != Some(id));
+    }
+
+    // Drops every pipeline so they're built from the reloaded shader modules on their next use.
+    fn rebuild_pipelines(&mut self) {
+        self.pipelines.clear();
+        self.shader_variants.clear();
+        self.post.clear_pipelines();
+        self.heat_maps.clear_pipelines();
+    }
+
+    // Compiles the ui.wgsl variant a pipeline needs, once per variant.
+    fn prepare_shader_variant(&mut self, r_state: &WgpuState, key: PipelineKey2D) {
+        let variant = ShaderVariant2D::new(&key);
+        if variant.is_plain() || self.shader_variants.contains_key(&variant) {
+            return;
+        }
+        let result = match variant.material.and_then(|id| self.materials.get(&id)) {
+            Some(material) => material.create_module(r_state, &variant.defines()),
+            None => r_state.gpu.shader_modules.borrow().create_variant(&r_state.gpu.device, r_state.gpu.shader_modules.borrow().get_source("ui.wgsl"), "ui.wgsl", &variant.defines()),
+        };
+        if let Err(err) = &result {
+            log::error!("{:?} failed to compile, drawing it with the plain shader:\n{}", variant, err);
+        }
+        self.shader_variants.insert(variant, result.ok());
+    }
+
+    fn create_pipeline(&self, r_state: &WgpuState, key: PipelineKey2D) -> RenderPipeline {
+        let variant = ShaderVariant2D::new(&key);
+        let plain_entry = if key.layer_composite { "fragment_layer" } else { "fragment" };
+        // Material pipelines keep their layout even without the material's shader, group 2 is still bound for them.
+        let layout = if key.material.is_some() { &self.material_pipeline_layout } else { &self.pipeline_layout };
+        let (module, entry_point) = match self.shader_variants.get(&variant) {
+            Some(Some(module)) => (module, if key.material.is_some() { "fragment_material" } else { plain_entry }),
+            _ => (&r_state.gpu.shader_modules.borrow().ui_shader, plain_entry),
+        };
+        return r_state.gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &r_state.shader_modules.ui_shader,
+                module,
                 entry_point: "vertex",
                 buffers: &[
                     UIVertex::describe(),
                 ],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &r_state.shader_modules.ui_shader,
-                entry_point: "fragment",
-                targets: &[Some(r_state.color_target.clone())],
+                module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    blend: Some(key.blend_mode.to_blend_state()),
+                    ..r_state.color_target.clone()
+                })],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                ..Default::default()
+            },
             multiview: None,
         });
+    }
 
-        let quad_slice: &[u16] = &[
-            0, 1, 2, 2, 3, 0
-        ];
-        let quad_index_buffer = r_state.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Quad Index Buffer"),
-            contents: bytemuck::cast_slice(quad_slice),
-            usage: BufferUsages::INDEX,
+    // Makes sure the MSAA texture exists and matches the frame size.
+    fn prepare_multisample_target(&mut self, device: &Device, width: u32, height: u32) {
+        if self.sample_count == 1 {
+            self.multisample_target = None;
+            return;
+        }
+        if let Some(target) = &self.multisample_target {
+            if target.width == width && target.height == height {
+                return;
+            }
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D Multisample Target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        self.multisample_target = Some(MultisampleTarget2D {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            width,
+            height,
         });
-
-        // Set up font rendering
-        // Prepare glyph_brush
-        let montserrat = FontArc::try_from_slice(include_bytes!(
-            "../Montserrat-Light.ttf"
-        ));
-
-        let debug_glyph_brush = GlyphBrushBuilder::using_font(montserrat.expect(""))
-            .build(&r_state.device, r_state.swapchain_format);
-
-        return Pipeline2D {
-            root_id: root.get_id(),
-            pipeline_2d: Box::new(pipeline_2d),
-            quad_index_buffer,
-            current_buffer: r_state.device.create_buffer_init(&BufferInitDescriptor{label: None, contents: &[], usage: BufferUsages::VERTEX}),
-            debug_glyph_brush,
-            debug_glyph_staging_belt: StagingBelt::new(1024),
-        };
     }
 
-    pub fn draw<'a>(&'a mut self, r_state: &mut WgpuState, encoder: &mut CommandEncoder, view: &TextureView, root: &mut dyn Drawable2D) {
-        //let pipeline_2d = &self.pipeline_2d;
-        if root.get_id() != self.root_id {
-            panic!("Pipeline2D::draw was called using a different container than expected!");
+    // Makes sure the layer has a texture the size of the frame, a new texture has nothing cached in it.
+    fn prepare_layer_target(&mut self, device: &Device, id: DrawableId, width: u32, height: u32) {
+        if let Some(target) = self.layer_targets.get(&id) {
+            if target.width == width && target.height == height {
+                return;
+            }
         }
-        let window_size = &r_state.get_size();
-        let mut rpass_quad: RenderPass<'_> = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D Layer Target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("2D Layer Bind Group"),
+            layout: &self.gpu.atlas.borrow().bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
                 },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.layer_sampler),
+                },
+            ],
         });
-        rpass_quad.set_pipeline(self.pipeline_2d.as_ref());
-        let mut rpass_text: RenderPass<'_> = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
+        self.layer_targets.insert(id, LayerTarget2D {
+            view,
+            bind_group,
+            width,
+            height,
+            content_hash: None,
+        });
+    }
+
+    // Draws a single root, other registered roots aren't drawn. Like draw_roots it can only be called once per frame.
+    pub fn draw(&mut self, r_state: &mut WgpuState, encoder: &mut CommandEncoder, view: &TextureView, root: &mut dyn Drawable2D) {
+        self.draw_roots(r_state, encoder, view, &mut [root]);
+    }
+
+    // Draws the roots into one frame in the order they were registered with, whatever order they're passed in.
+    // Post effects and text apply to all of them together.
+    // Only call it once per frame: the vertices of every root share one buffer and the first pass clears the frame,
+    // so a second call would overwrite and clear what the first one drew. Pass all the roots in one call instead.
+    pub fn draw_roots(&mut self, r_state: &mut WgpuState, encoder: &mut CommandEncoder, view: &TextureView, roots: &mut [&mut dyn Drawable2D]) {
+        let drawing_order = self.drawing_order(roots);
+        if r_state.gpu.shader_modules.borrow().get_generation() != self.shader_generation {
+            self.shader_generation = r_state.gpu.shader_modules.borrow().get_generation();
+            self.rebuild_pipelines();
+        }
+        // Images can't be added or removed while a frame is recorded.
+        let gpu = self.gpu.clone();
+        let atlas = gpu.atlas.borrow();
+        // The previous frame has been submitted by now, so the belt's buffers can be reused.
+        self.debug_glyph_staging_belt.recall();
+        let layout_start = Instant::now();
+        let mut stats = RenderStats2D::default();
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin_frame(&r_state.gpu.device);
+        }
+        // The tree is laid out in logical pixels, render targets and glyphs are in physical ones.
+        let window_size = r_state.get_logical_size();
+        let scale_factor = r_state.get_scale_factor();
+        let (width, height) = (r_state.config.width, r_state.config.height);
+
+        // Vertices stay in logical pixels, the shader applies either the plain screen projection or a root's camera on top of it.
+        let screen_projection = Camera2D::screen_projection(window_size);
+        let mut projections = vec![screen_projection];
+        while self.views.len() < roots.len() + 1 {
+            self.views.push(ViewUniform2D::new(&r_state.gpu.device, &self.view_layout, "2D World View"));
+        }
+        self.views[SCREEN_VIEW].write(r_state, screen_projection);
+
+        let mut root_draws: Vec<(Vec<DrawNode2D>, BuildContext2D)> = vec![];
+        for (i, root_index) in drawing_order.into_iter().enumerate() {
+            let root = &mut roots[root_index];
+            let camera = self.get_root_camera(root.get_id());
+            let world_projection = screen_projection * camera.to_view_matrix();
+            self.views[i + 1].write(r_state, world_projection);
+            projections.push(world_projection);
+
+            let context = BuildContext2D {
+                world_view: i + 1,
+                camera,
+                world_projection,
+                analytic_aa: self.sample_count == 1,
+                sample_count: self.sample_count,
+                window_size,
+                scale_factor,
+                allow_backdrops: true,
+            };
+            let nodes = root.draw().unwrap_or_default();
+            let nodes = apply_drawable_state(&mut **root, nodes);
+            root_draws.push((nodes, context));
+        }
+
+        // Everything, layers and every root included, is put into one vertex buffer since buffer writes only land at submit.
+        let mut vertices: Vec<UIVertex> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        let mut layers: Vec<LayerJob2D> = vec![];
+        let mut frame_list = DrawList2D::new();
+        for (nodes, context) in &root_draws {
+            self.build_draw_list(nodes, *context, &mut frame_list, &mut vertices, &mut indices, &mut layers);
+        }
+        let mut flattened: Vec<&DrawNode2D> = vec![];
+        for (nodes, _) in &root_draws {
+            flatten_nodes(nodes, &mut flattened);
+        }
+        stats.draw_nodes = flattened.len();
+        stats.vertices = vertices.len();
+        stats.indices = indices.len();
+        stats.uploaded_bytes = (vertices.len() * std::mem::size_of::<UIVertex>() + indices.len() * 4 + projections.len() * std::mem::size_of::<Matrix4<f32>>()) as u64;
+        stats.layout_time = layout_start.elapsed();
+        let encode_start = Instant::now();
+
+        Self::write_buffer(r_state, &mut self.vertex_buffer, "2D Vertex Buffer", BufferUsages::VERTEX, bytemuck::cast_slice(&vertices));
+        Self::write_buffer(r_state, &mut self.index_buffer, "2D Index Buffer", BufferUsages::INDEX, bytemuck::cast_slice(&indices));
+
+        self.prepare_multisample_target(&r_state.gpu.device, width, height);
+        // Layers that weren't drawn this frame give their texture back.
+        self.layer_targets.retain(|id, _| layers.iter().any(|layer| layer.id == *id));
+        for layer in &layers {
+            self.prepare_layer_target(&r_state.gpu.device, layer.id, width, height);
+        }
+        let heat_maps: Vec<&HeatMapJob2D> = frame_list.heat_maps.iter().chain(layers.iter().flat_map(|layer| layer.list.heat_maps.iter())).collect();
+        self.heat_maps.retain(&heat_maps);
+        for job in heat_maps {
+            self.heat_maps.prepare(r_state, job, projections[job.view]);
+        }
+        for batch in frame_list.batches.iter().chain(layers.iter().flat_map(|layer| layer.list.batches.iter())) {
+            if !self.pipelines.contains_key(&batch.pipeline) {
+                self.prepare_shader_variant(r_state, batch.pipeline);
+                let pipeline = self.create_pipeline(r_state, batch.pipeline);
+                self.pipelines.insert(batch.pipeline, pipeline);
+            }
+        }
+
+        // Layers come out innermost first, so every layer texture is ready before anything composites it.
+        for layer in layers {
+            // Taken out of the map while it's the render target, a layer never samples itself.
+            let mut target = self.layer_targets.remove(&layer.id).expect("");
+            if !layer.cached || target.content_hash != Some(layer.content_hash) {
+                {
+                    let mut rpass_layer: RenderPass<'_> = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("2D Layer Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &target.view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: self.gpu_timer.as_ref().and_then(|timer| timer.pass_writes()),
+                        occlusion_query_set: None,
+                    });
+                    stats.draw_calls += self.draw_batches(&atlas, &mut rpass_layer, &layer.list.batches);
+                }
+                Self::draw_sections(&mut self.debug_glyph_brush, &mut self.debug_glyph_staging_belt, r_state, encoder, &target.view, layer.list, &projections);
+                target.content_hash = Some(layer.content_hash);
+            }
+            self.layer_targets.insert(layer.id, target);
+        }
+
+        // Effects and backdrops need the frame in a texture they can sample, otherwise it's drawn straight to the frame.
+        // Frames that have to be encoded by hand always go through the post processor, its last pass does the encoding.
+        let post_active = !self.post_effects.is_empty() || !frame_list.backdrops.is_empty() || r_state.encode_srgb;
+        if post_active {
+            self.post.begin_frame(r_state, &atlas.bind_group_layout, &self.post_effects, frame_list.backdrops.len());
+        }
+
+        // A backdrop needs everything before it finished, so the frame is split into one pass per backdrop.
+        // The first pass clears the scene even when it draws nothing, so a backdrop at the start doesn't blur the last frame.
+        let mut segments: Vec<Range<usize>> = Vec::with_capacity(frame_list.backdrops.len() + 1);
+        segments.push(0..0);
+        for (i, batch) in frame_list.batches.iter().enumerate() {
+            match segments.last_mut() {
+                Some(segment) if !matches!(batch.texture, TextureSource2D::Backdrop(_)) => segment.end = i + 1,
+                _ => segments.push(i..i + 1),
+            }
+        }
+        let segment_count = segments.len();
+        for (i, segment) in segments.into_iter().enumerate() {
+            if let Some(TextureSource2D::Backdrop(backdrop)) = frame_list.batches.get(segment.start).map(|batch| batch.texture) {
+                self.post.blur_backdrop(encoder, frame_list.backdrops[backdrop], self.gpu_timer.as_ref());
+            }
+            let scene_view = if post_active { self.post.target_view(SCENE_TARGET) } else { view };
+            // With MSAA the samples are resolved into the scene and only thrown away after the last pass.
+            let (target_view, resolve_target, store) = match &self.multisample_target {
+                Some(target) => (&target.view, Some(scene_view), if i == segment_count - 1 { wgpu::StoreOp::Discard } else { wgpu::StoreOp::Store }),
+                None => (scene_view, None, wgpu::StoreOp::Store),
+            };
+            let mut rpass_quad: RenderPass<'_> = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target_view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: if i == 0 { wgpu::LoadOp::Clear(wgpu::Color::BLACK) } else { wgpu::LoadOp::Load },
+                        store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: self.gpu_timer.as_ref().and_then(|timer| timer.pass_writes()),
+                occlusion_query_set: None,
+            });
+            stats.draw_calls += self.draw_batches(&atlas, &mut rpass_quad, &frame_list.batches[segment]);
+        }
+
+        // Text goes on top of everything else in its own pass.
+        let scene_view = if post_active { self.post.target_view(SCENE_TARGET) } else { view };
+        Self::draw_sections(&mut self.debug_glyph_brush, &mut self.debug_glyph_staging_belt, r_state, encoder, scene_view, frame_list, &projections);
+        // The overlay goes over the effects. A frame that's encoded by hand has another format than the text is drawn in,
+        // so there it goes on the result of the effects before that's encoded.
+        if post_active {
+            if let Some(output) = self.post.run_effects(encoder, &self.post_effects, view, self.gpu_timer.as_ref()) {
+                if self.stats_overlay {
+                    Self::draw_overlay(&mut self.debug_glyph_brush, &mut self.debug_glyph_staging_belt, r_state, encoder, self.post.target_view(output), &self.stats);
+                }
+                self.post.encode(encoder, output, view, self.gpu_timer.as_ref());
+            }
+            self.post.finish(r_state);
+            stats.draw_calls += self.post.get_pass_count();
+        }
+        if self.stats_overlay && !r_state.encode_srgb {
+            Self::draw_overlay(&mut self.debug_glyph_brush, &mut self.debug_glyph_staging_belt, r_state, encoder, view, &self.stats);
+        }
+        self.debug_glyph_staging_belt.finish();
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.resolve(encoder);
+            stats.gpu_time = timer.get_last_time();
+        }
+        stats.encode_time = encode_start.elapsed();
+        self.stats = stats;
+    }
+
+    // Turns nodes into vertices and batches. A layer gets a draw list of its own and only a single quad in this one.
+    fn build_draw_list<'n>(&self, nodes: &'n [DrawNode2D], context: BuildContext2D, list: &mut DrawList2D<'n>, vertices: &mut Vec<UIVertex>, indices: &mut Vec<u32>, layers: &mut Vec<LayerJob2D<'n>>) {
+        let atlas = self.gpu.atlas.borrow();
+        let white_uv = atlas.white_uv();
+        for node in nodes {
+            let world_space = node.get_info().world_space;
+            let key = BatchKey2D {
+                texture: None,
+                view: if world_space { context.world_view } else { SCREEN_VIEW },
+                pipeline: PipelineKey2D {
+                    blend_mode: node.get_info().blend_mode.unwrap_or_default(),
+                    layer_composite: false,
+                    material: node.get_info().material.filter(|id| self.materials.contains_key(id)),
+                    rounded_corners: false,
+                    sample_count: context.sample_count,
                 },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-        let draw_result = root.draw();
-        let mut current_draws: Vec<DrawNode2D> = if draw_result.is_some() { draw_result.expect("") } else { vec![] };
-
-        let mut current_buffer: &'a mut Buffer;
-        {
-            for node in &mut current_draws {
-                match node {
-                    DrawNode2D::Quad(quad) => {
-                        let window_size = &r_state.get_size();
-                        let mut vbuf: [UIVertex; 4] = [
-                            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x, quad.quad.abs_pos.y, 1.0]},
-                            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x + quad.quad.abs_size.x, quad.quad.abs_pos.y, 1.0]},
-                            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x + quad.quad.abs_size.x, quad.quad.abs_pos.y + quad.quad.abs_size.y, 1.0]},
-                            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x, quad.quad.abs_pos.y + quad.quad.abs_size.y, 1.0]}
-                        ];
-                        for mut ele in &mut vbuf {
-                            ele.pos[0] = (ele.pos[0] / (window_size.x as f32)) * 2.0 - 1.0;
-                            ele.pos[1] = -((ele.pos[1] / (window_size.y as f32)) * 2.0 - 1.0);//-2 so that we can have Y-down
-                        }
-                        {
-                            self.current_buffer = r_state.device.create_buffer_init(&BufferInitDescriptor {
-                                label: Some("Vertex Buffer"),
-                                contents: bytemuck::cast_slice(&vbuf),
-                                usage: BufferUsages::VERTEX,
-                            });
-                        }
-                        {
-                            rpass_quad.set_vertex_buffer(0, self.current_buffer.slice(..));
-                            rpass_quad.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                            rpass_quad.draw_indexed(0..6, 0, 0..1);
-                        }
+            };
+            match node {
+                DrawNode2D::Quad(quad) => {
+                    let rounded_key = BatchKey2D { pipeline: PipelineKey2D { rounded_corners: quad.corner_radius > 0.0, ..key.pipeline }, ..key };
+                    Self::push_batch(&mut list.batches, rounded_key, indices.len() as u32);
+                    push_quad(vertices, indices, &quad.quad, &quad.info, QuadFill2D { uv_min: white_uv, uv_max: white_uv, color: quad.color.into(), radius: quad.corner_radius }, context.analytic_aa);
+                }
+                DrawNode2D::Image(image) => {
+                    let region = match atlas.get_region(image.image) {
+                        Some(region) => region,
+                        None => continue,
+                    };
+                    let image_key = BatchKey2D {
+                        texture: Some(TextureSource2D::Atlas(region.page)),
+                        pipeline: PipelineKey2D { rounded_corners: image.corner_radius > 0.0, ..key.pipeline },
+                        ..key
+                    };
+                    Self::push_batch(&mut list.batches, image_key, indices.len() as u32);
+                    let uv_size = [region.uv_max[0] - region.uv_min[0], region.uv_max[1] - region.uv_min[1]];
+                    let uv_min = [region.uv_min[0] + image.uv_rect[0] * uv_size[0], region.uv_min[1] + image.uv_rect[1] * uv_size[1]];
+                    let uv_max = [uv_min[0] + image.uv_rect[2] * uv_size[0], uv_min[1] + image.uv_rect[3] * uv_size[1]];
+                    push_quad(vertices, indices, &image.transform, &image.info, QuadFill2D { uv_min, uv_max, color: image.color.into(), radius: image.corner_radius }, context.analytic_aa);
+                }
+                DrawNode2D::Mesh(mesh) => {
+                    if context.analytic_aa && !self.warned_aliased_meshes.replace(true) {
+                        log::warn!("Paths have aliased edges without MSAA, set a sample count above 1 to smooth them");
                     }
-                    DrawNode2D::Text(text) => {
-                        self.debug_glyph_brush.queue(Section {
-                            screen_position: (text.transform.abs_pos.x, text.transform.abs_pos.y),
-                            bounds: (r_state.get_width() as f32, r_state.get_height() as f32),
-                            text: vec![Text::new(text.text.as_str())
-                                .with_color(text.color.to_array())
-                                .with_scale(text.scale)],
-                            ..Section::default()
-                        });
-                        /*self.debug_glyph_brush
-                        .draw_queued(
-                            &r_state.device,
-                            &mut self.debug_glyph_staging_belt,
-                            encoder,
-                            view,
-                            window_size.x as u32,
-                            window_size.y as u32,
-                        );*/
+                    Self::push_batch(&mut list.batches, key, indices.len() as u32);
+                    push_mesh(vertices, indices, mesh, white_uv);
+                }
+                DrawNode2D::Text(text) => {
+                    // Glyphs can't be rotated by the brush, only their position follows the affine transforms.
+                    // Sections are laid out in physical pixels so glyphs are rasterized at the size they're shown at.
+                    let position = text.info.get_matrix() * Vector3::new(text.transform.abs_pos.x, text.transform.abs_pos.y, 1.0);
+                    let section = Section {
+                        screen_position: (position.x * context.scale_factor, position.y * context.scale_factor),
+                        bounds: (context.window_size.x * context.scale_factor, context.window_size.y * context.scale_factor),
+                        text: vec![Text::new(text.text.as_str())
+                            .with_color(text.color.to_linear_array())
+                            .with_scale(text.scale * context.scale_factor)],
+                        ..Section::default()
+                    };
+                    if world_space {
+                        list.world_sections.push((context.world_view, section));
+                    } else {
+                        list.screen_sections.push(section);
+                    }
+                }
+                DrawNode2D::Layer(layer) => {
+                    // Layer textures are single sampled, the layer's own blend mode is only used to composite it.
+                    let layer_context = BuildContext2D {
+                        analytic_aa: true,
+                        sample_count: 1,
+                        allow_backdrops: false,
+                        ..context
+                    };
+                    let first_vertex = vertices.len();
+                    let first_index = indices.len();
+                    let first_layer = layers.len();
+                    let mut layer_list = DrawList2D::new();
+                    self.build_draw_list(&layer.nodes, layer_context, &mut layer_list, vertices, indices, layers);
+                    let content_hash = Self::hash_layer(&self.materials, &vertices[first_vertex..], &indices[first_index..], first_vertex as u32, &layer_list, &layers[first_layer..], &context.world_projection);
+                    layers.push(LayerJob2D {
+                        id: layer.info.id,
+                        cached: layer.cached,
+                        content_hash,
+                        list: layer_list,
+                    });
+
+                    let composite_key = BatchKey2D {
+                        texture: Some(TextureSource2D::Layer(layer.info.id)),
+                        view: SCREEN_VIEW,
+                        pipeline: PipelineKey2D {
+                            layer_composite: true,
+                            material: None,
+                            ..key.pipeline
+                        },
+                    };
+                    Self::push_batch(&mut list.batches, composite_key, indices.len() as u32);
+                    let mut screen = TransformState2D::new();
+                    screen.abs_size = context.window_size;
+                    // The texture is premultiplied, so opacity scales every channel.
+                    push_quad(vertices, indices, &screen, &NodeInfo2D::new(layer.info.id), QuadFill2D { uv_min: [0.0, 0.0], uv_max: [1.0, 1.0], color: [layer.opacity; 4], radius: 0.0 }, false);
+                }
+                DrawNode2D::HeatMap(heat_map) => {
+                    if heat_map.grid.width == 0 || heat_map.grid.height == 0 {
+                        continue;
+                    }
+                    Self::push_batch(&mut list.batches, BatchKey2D { texture: Some(TextureSource2D::HeatMap(heat_map.info.id)), ..key }, indices.len() as u32);
+                    list.heat_maps.push(HeatMapJob2D {
+                        id: heat_map.info.id,
+                        grid: heat_map.grid.clone(),
+                        corners: heat_map_corners(heat_map),
+                        view: key.view,
+                        blend_mode: key.pipeline.blend_mode,
+                        sample_count: context.sample_count,
+                    });
+                }
+                DrawNode2D::Backdrop(backdrop) => {
+                    // Layers are drawn on their own, there's nothing behind them to blur.
+                    if !context.allow_backdrops {
+                        continue;
+                    }
+                    let texture = TextureSource2D::Backdrop(list.backdrops.len());
+                    list.backdrops.push(backdrop.radius);
+                    let backdrop_key = BatchKey2D {
+                        texture: Some(texture),
+                        pipeline: PipelineKey2D {
+                            material: None,
+                            ..key.pipeline
+                        },
+                        ..key
+                    };
+                    Self::push_batch(&mut list.batches, backdrop_key, indices.len() as u32);
+                    let first_vertex = vertices.len();
+                    push_quad(vertices, indices, &backdrop.transform, &backdrop.info, QuadFill2D { uv_min: [0.0, 0.0], uv_max: [0.0, 0.0], color: [1.0; 4], radius: 0.0 }, context.analytic_aa);
+                    // The blurred frame is sampled where the quad ends up on screen.
+                    for vertex in &mut vertices[first_vertex..] {
+                        let screen = if world_space { context.camera.world_to_screen(vertex.pos[0], vertex.pos[1]) } else { Vector2::new(vertex.pos[0], vertex.pos[1]) };
+                        vertex.uv = [screen.x / context.window_size.x, screen.y / context.window_size.y];
                     }
                 }
             }
+            if let Some(batch) = list.batches.last_mut() {
+                batch.indices.end = indices.len() as u32;
+            }
         }
+    }
 
-        /*self.debug_glyph_brush.queue(Section {
-            screen_position: (30.0, 30.0),
-            bounds: (r_state.get_width() as f32, r_state.get_height() as f32),
-            text: vec![Text::new("Hello world!")
-                .with_color([1.0, 0.0, 0.0, 1.0])
-                .with_scale(40.0)],
+    // Everything that ends up in a layer texture, so a cached layer knows when it has to be drawn again.
+    // Layers nested inside it are composited from their own textures, so their hashes stand in for what they draw.
+    fn hash_layer(materials: &HashMap<MaterialId, Material2D>, vertices: &[UIVertex], indices: &[u32], first_vertex: u32, list: &DrawList2D, nested: &[LayerJob2D], world_projection: &Matrix4<f32>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        bytemuck::cast_slice::<UIVertex, u8>(vertices).hash(&mut hasher);
+        for index in indices {
+            (index - first_vertex).hash(&mut hasher);
+        }
+        for batch in &list.batches {
+            batch.texture.hash(&mut hasher);
+            batch.pipeline.hash(&mut hasher);
+            batch.view.hash(&mut hasher);
+            if let Some(material) = batch.pipeline.material.and_then(|id| materials.get(&id)) {
+                material.get_version().hash(&mut hasher);
+            }
+        }
+        for section in list.screen_sections.iter().chain(list.world_sections.iter().map(|(_, section)| section)) {
+            section.screen_position.0.to_bits().hash(&mut hasher);
+            section.screen_position.1.to_bits().hash(&mut hasher);
+            for text in &section.text {
+                text.text.hash(&mut hasher);
+                text.scale.x.to_bits().hash(&mut hasher);
+                for channel in text.extra.color {
+                    channel.to_bits().hash(&mut hasher);
+                }
+            }
+        }
+        for heat_map in &list.heat_maps {
+            heat_map.grid.version.hash(&mut hasher);
+            for corner in heat_map.corners {
+                corner.x.to_bits().hash(&mut hasher);
+                corner.y.to_bits().hash(&mut hasher);
+            }
+        }
+        for layer in nested {
+            layer.id.hash(&mut hasher);
+            layer.content_hash.hash(&mut hasher);
+        }
+        // World space content moves with the camera.
+        if list.batches.iter().any(|batch| batch.view != SCREEN_VIEW) || !list.world_sections.is_empty() {
+            let matrix: &[f32; 16] = world_projection.as_ref();
+            bytemuck::cast_slice::<f32, u8>(matrix).hash(&mut hasher);
+        }
+        return hasher.finish();
+    }
+
+    // Returns the number of draw calls.
+    fn draw_batches<'r>(&'r self, atlas: &'r TextureAtlas, rpass: &mut RenderPass<'r>, batches: &[DrawBatch2D]) -> u32 {
+        // Heat maps bind their own buffers, so ours are bound again after one.
+        let mut bind_buffers = true;
+        let mut draw_calls = 0;
+        for batch in batches {
+            let texture = match batch.texture {
+                TextureSource2D::Atlas(page) => atlas.get_bind_group(page),
+                TextureSource2D::Layer(id) => &self.layer_targets[&id].bind_group,
+                TextureSource2D::Backdrop(_) => self.post.target_bind_group(BACKDROP_TARGET),
+                TextureSource2D::HeatMap(id) => {
+                    draw_calls += self.heat_maps.draw(rpass, id);
+                    bind_buffers = true;
+                    continue;
+                }
+            };
+            if bind_buffers {
+                rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                bind_buffers = false;
+            }
+            rpass.set_pipeline(&self.pipelines[&batch.pipeline]);
+            rpass.set_bind_group(0, texture, &[]);
+            rpass.set_bind_group(1, &self.views[batch.view].bind_group, &[]);
+            if let Some(material) = batch.pipeline.material.and_then(|id| self.materials.get(&id)) {
+                rpass.set_bind_group(2, &material.bind_group, &[]);
+            }
+            rpass.draw_indexed(batch.indices.clone(), 0, 0..1);
+            draw_calls += 1;
+        }
+        return draw_calls;
+    }
+
+    // Takes the brush and belt instead of self so the view can come from one of Pipeline2D's own textures.
+    fn draw_overlay(glyph_brush: &mut GlyphBrush<()>, staging_belt: &mut StagingBelt, r_state: &WgpuState, encoder: &mut CommandEncoder, view: &TextureView, stats: &RenderStats2D) {
+        let scale_factor = r_state.get_scale_factor();
+        let (width, height) = (r_state.config.width, r_state.config.height);
+        let summary = stats.summary();
+        glyph_brush.queue(Section {
+            screen_position: (8.0 * scale_factor, 8.0 * scale_factor),
+            bounds: (width as f32, height as f32),
+            text: vec![Text::new(summary.as_str())
+                .with_color([1.0, 1.0, 1.0, 1.0])
+                .with_scale(16.0 * scale_factor)],
             ..Section::default()
         });
+        glyph_brush
+            .draw_queued(&r_state.gpu.device, staging_belt, encoder, view, width, height)
+            .expect("Draw queued");
+    }
 
-        self.debug_glyph_brush
-        .draw_queued(
-            &r_state.device,
-            &mut self.debug_glyph_staging_belt,
-            encoder,
-            view,
-            window_size.x as u32,
-            window_size.y as u32,
-        )
-        .expect("Draw queued");
-        self.debug_glyph_staging_belt.finish();*/
-        //self.debug_glyph_staging_belt.recall();
-    }
-
-    fn draw_quad<'a>(&'a mut self, quad: &mut QuadDrawNode2D, r_state: &mut WgpuState, mut rpass: RenderPass<'a>) {
-        let window_size = &r_state.get_size();
-        let mut vbuf: [UIVertex; 4] = [
-            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x, quad.quad.abs_pos.y, 1.0]},
-            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x + quad.quad.abs_size.x, quad.quad.abs_pos.y, 1.0]},
-            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x + quad.quad.abs_size.x, quad.quad.abs_pos.y + quad.quad.abs_size.y, 1.0]},
-            UIVertex {uv: [0.0, 0.0], color: [quad.color.x, quad.color.y, quad.color.z, quad.color.w], pos: [quad.quad.abs_pos.x, quad.quad.abs_pos.y + quad.quad.abs_size.y, 1.0]}
-        ];
-        for mut ele in &mut vbuf {
-            ele.pos[0] = (ele.pos[0] / (window_size.x as f32)) * 2.0 - 1.0;
-            ele.pos[1] = -((ele.pos[1] / (window_size.y as f32)) * 2.0 - 1.0);//-2 so that we can have Y-down
-        }
-        self.current_buffer = r_state.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vbuf),
-            usage: BufferUsages::VERTEX,
-        });
-        rpass.set_vertex_buffer(0, self.current_buffer.slice(..));
-        rpass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        rpass.draw_indexed(0..6, 0, 0..1);
+    fn draw_sections(glyph_brush: &mut GlyphBrush<()>, staging_belt: &mut StagingBelt, r_state: &WgpuState, encoder: &mut CommandEncoder, view: &TextureView, list: DrawList2D, projections: &[Matrix4<f32>]) {
+        let scale_factor = r_state.get_scale_factor();
+        if !list.screen_sections.is_empty() {
+            for section in list.screen_sections {
+                glyph_brush.queue(section);
+            }
+            glyph_brush
+                .draw_queued(
+                    &r_state.gpu.device,
+                    staging_belt,
+                    encoder,
+                    view,
+                    r_state.config.width,
+                    r_state.config.height,
+                )
+                .expect("Draw queued");
+        }
+        // Every run of sections seen through the same camera is drawn with its transform.
+        let mut sections = list.world_sections.into_iter().peekable();
+        while let Some((view_index, section)) = sections.next() {
+            glyph_brush.queue(section);
+            if sections.peek().map(|(next, _)| *next) == Some(view_index) {
+                continue;
+            }
+            glyph_brush
+                .draw_queued_with_transform(
+                    &r_state.gpu.device,
+                    staging_belt,
+                    encoder,
+                    view,
+                    // Sections are in physical pixels, the camera works in logical ones.
+                    *(projections[view_index] * Matrix4::from_nonuniform_scale(1.0 / scale_factor, 1.0 / scale_factor, 1.0)).as_ref(),
+                )
+                .expect("Draw queued");
+        }
+    }
+
+    // Returns the drawable whose node is top-most under the window point in logical pixels, world space nodes are tested through the root's camera.
+    pub fn hit_test(&self, root: &mut dyn Drawable2D, x: f32, y: f32) -> Option<DrawableId> {
+        let camera = self.get_root_camera(root.get_id());
+        let nodes = root.draw()?;
+        let nodes = apply_drawable_state(root, nodes);
+        let mut flattened: Vec<&DrawNode2D> = vec![];
+        flatten_nodes(&nodes, &mut flattened);
+        let screen_point = Vector2::new(x, y);
+        let world_point = camera.screen_to_world(x, y);
+        return flattened.iter().rev()
+            .find(|node| {
+                node.contains_point(if node.get_info().world_space { world_point } else { screen_point })
+            })
+            .map(|node| node.get_info().id);
+    }
+
+    // Like hit_test, trying the roots drawn on top first.
+    pub fn hit_test_roots(&self, roots: &mut [&mut dyn Drawable2D], x: f32, y: f32) -> Option<DrawableId> {
+        let drawing_order = self.drawing_order(roots);
+        return drawing_order.into_iter().rev().find_map(|i| self.hit_test(&mut *roots[i], x, y));
+    }
+
+    // Indices into roots in the order they're drawn. Roots that weren't registered with add_root are left out.
+    fn drawing_order(&self, roots: &mut [&mut dyn Drawable2D]) -> Vec<usize> {
+        let mut positions: Vec<(usize, usize)> = roots.iter_mut().enumerate().filter_map(|(i, root)| {
+            let id = root.get_id();
+            match self.roots.iter().position(|entry| entry.id == id) {
+                Some(position) => Some((position, i)),
+                None => {
+                    log::warn!("Skipping drawable {:?}, it isn't one of Pipeline2D's roots", id);
+                    None
+                }
+            }
+        }).collect();
+        positions.sort();
+        return positions.into_iter().map(|(_, i)| i).collect();
+    }
+
+    // Starts a new batch when the node needs something bound that the current batch doesn't have.
+    fn push_batch(batches: &mut Vec<DrawBatch2D>, key: BatchKey2D, index_start: u32) {
+        match batches.last() {
+            Some(batch) if batch.view == key.view && batch.pipeline == key.pipeline
+                && (key.texture == Some(batch.texture) || (key.texture.is_none() && matches!(batch.texture, TextureSource2D::Atlas(_)))) => {}
+            _ => {
+                // Nodes that only use the white texel keep the atlas page that's bound, never a layer texture.
+                let open_page = batches.last().map(|batch| batch.texture).filter(|texture| matches!(texture, TextureSource2D::Atlas(_)));
+                batches.push(DrawBatch2D {
+                    texture: key.texture.or(open_page).unwrap_or(TextureSource2D::Atlas(0)),
+                    view: key.view,
+                    pipeline: key.pipeline,
+                    indices: index_start..index_start,
+                });
+            }
+        }
+    }
+
+    fn create_buffer(device: &Device, label: &str, size: u64, usage: BufferUsages) -> Buffer {
+        return device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: usage | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    }
+
+    // Grows the buffer when needed, buffers are only ever recreated when the data doesn't fit.
+    fn write_buffer(r_state: &WgpuState, buffer: &mut Buffer, label: &str, usage: BufferUsages, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if data.len() as u64 > buffer.size() {
+            *buffer = Self::create_buffer(&r_state.gpu.device, label, (data.len() as u64).next_power_of_two(), usage);
+        }
+        r_state.gpu.queue.write_buffer(buffer, 0, data);
     }
 
+    // Takes the new surface size in physical pixels.
     pub fn on_resized(&mut self, width: u32, height: u32, device: &Device) {
-        //self.screen_size_bind_group = Pipeline2D::create_screen_size_bind_group(width, height, &Pipeline2D::get_screen_size_layout(device), device)
+        self.prepare_multisample_target(device, width, height);
+        let layer_ids: Vec<DrawableId> = self.layer_targets.keys().copied().collect();
+        for id in layer_ids {
+            self.prepare_layer_target(device, id, width, height);
+        }
+    }
+}
+
+// With analytic AA the quad grows by AA_MARGIN on every side and the shader fades out everything outside the real edge.
+// Rounded corners are cut off in the shader the same way, so rounded quads always get it.
+fn push_quad(vertices: &mut Vec<UIVertex>, indices: &mut Vec<u32>, transform: &TransformState2D, info: &NodeInfo2D, fill: QuadFill2D, analytic_aa: bool) {
+    let QuadFill2D { uv_min, uv_max, color, radius } = fill;
+    let first = vertices.len() as u32;
+    let analytic_aa = analytic_aa || radius > 0.0;
+    let size = transform.abs_size;
+    let margin = if analytic_aa && size.x > 0.0 && size.y > 0.0 { AA_MARGIN } else { 0.0 };
+    // How much the uv changes per pixel, used to stretch the uvs over the margin.
+    let uv_per_pixel = if margin > 0.0 {
+        [(uv_max[0] - uv_min[0]) / size.x, (uv_max[1] - uv_min[1]) / size.y]
+    } else {
+        [0.0, 0.0]
+    };
+    let corner = |x: f32, y: f32| {
+        let local = [x * (size.x + margin * 2.0) - margin, y * (size.y + margin * 2.0) - margin];
+        return UIVertex {
+            uv: [uv_min[0] + (uv_max[0] - uv_min[0]) * x + uv_per_pixel[0] * (local[0] - x * size.x), uv_min[1] + (uv_max[1] - uv_min[1]) * y + uv_per_pixel[1] * (local[1] - y * size.y)],
+            color,
+            pos: [transform.abs_pos.x + local[0], transform.abs_pos.y + local[1], 1.0],
+            edge: if analytic_aa { [local[0], local[1], size.x, size.y] } else { [0.0, 0.0, -1.0, -1.0] },
+            radius,
+        };
+    };
+    let mut vbuf: [UIVertex; 4] = [
+        corner(0.0, 0.0),
+        corner(1.0, 0.0),
+        corner(1.0, 1.0),
+        corner(0.0, 1.0),
+    ];
+    let matrix = info.get_matrix();
+    for ele in &mut vbuf {
+        let corner = matrix * Vector3::new(ele.pos[0], ele.pos[1], 1.0);
+        ele.pos[0] = corner.x;
+        ele.pos[1] = corner.y;
+    }
+    vertices.extend_from_slice(&vbuf);
+    indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+}
+
+// Meshes have no edge information, with MSAA off their edges are aliased.
+fn push_mesh(vertices: &mut Vec<UIVertex>, indices: &mut Vec<u32>, node: &MeshDrawNode2D, uv: [f32; 2]) {
+    let first = vertices.len() as u32;
+    let matrix = node.info.get_matrix();
+    let offset = node.transform.abs_pos;
+    let color: [f32; 4] = node.color.into();
+    for vertex in &node.mesh.vertices {
+        let pos = matrix * Vector3::new(vertex.x + offset.x, vertex.y + offset.y, 1.0);
+        vertices.push(UIVertex {uv, color, pos: [pos.x, pos.y, 1.0], edge: [0.0, 0.0, -1.0, -1.0], radius: 0.0});
     }
+    indices.extend(node.mesh.indices.iter().map(|i| first + i));
 }
 
 #[repr(C)]
@@ -225,11 +1138,13 @@ impl Pipeline2D {
 struct UIVertex {
     uv: [f32; 2],
     color: [f32; 4],
-    pos: [f32; 3]
+    pos: [f32; 3],
+    edge: [f32; 4], // Position inside the quad and the quad size, in pixels. A negative size turns edge AA off
+    radius: f32, // Corner radius in pixels, only used by the ROUNDED_CORNERS variant
 }
 
 impl UIVertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4, 2 => Float32x3];
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4, 2 => Float32x3, 3 => Float32x4, 4 => Float32];
 
     fn describe() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
@@ -241,4 +1156,51 @@ impl UIVertex {
         }
         
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer_list(text: &str) -> DrawList2D<'_> {
+        let mut list = DrawList2D::new();
+        list.screen_sections.push(Section {
+            text: vec![Text::new(text)],
+            ..Section::default()
+        });
+        return list;
+    }
+
+    fn layer_job(id: DrawableId, list: DrawList2D) -> LayerJob2D {
+        let content_hash = Pipeline2D::hash_layer(&HashMap::new(), &[], &[], 0, &list, &[], &Matrix4::from_scale(1.0));
+        return LayerJob2D {
+            id,
+            cached: true,
+            content_hash,
+            list,
+        };
+    }
+
+    #[test]
+    fn a_nested_layer_changes_the_hash_of_the_layer_around_it() {
+        let id = DrawableId::dummy();
+        let outer = DrawList2D::new();
+        let hash = |nested: &[LayerJob2D]| Pipeline2D::hash_layer(&HashMap::new(), &[], &[], 0, &outer, nested, &Matrix4::from_scale(1.0));
+        let before = hash(&[layer_job(id, layer_list("before"))]);
+        assert_eq!(before, hash(&[layer_job(id, layer_list("before"))]));
+        assert_ne!(before, hash(&[layer_job(id, layer_list("after"))]));
+    }
+
+    #[test]
+    fn world_space_content_of_a_nested_layer_follows_the_camera() {
+        let id = DrawableId::dummy();
+        let outer = DrawList2D::new();
+        let nested = |projection: Matrix4<f32>| {
+            let mut list = DrawList2D::new();
+            list.world_sections.push((1, Section::default()));
+            let content_hash = Pipeline2D::hash_layer(&HashMap::new(), &[], &[], 0, &list, &[], &projection);
+            return Pipeline2D::hash_layer(&HashMap::new(), &[], &[], 0, &outer, &[LayerJob2D { id, cached: true, content_hash, list }], &Matrix4::from_scale(1.0));
+        };
+        assert_ne!(nested(Matrix4::from_scale(1.0)), nested(Matrix4::from_scale(2.0)));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{BindGroup, BindGroupLayout, Buffer, BufferUsages, CommandEncoder, Device, PipelineLayout, RenderPipeline, Sampler, ShaderModule, TextureFormat, TextureView};
+
+use crate::{math::color::Color, renderer::WgpuState};
+
+use super::render_stats_2d::GpuTimer2D;
+
+// Every fullscreen pass gets its own slot in the uniform buffer, offsets have to be aligned to this.
+const UNIFORM_SLOT: u64 = 256;
+
+// Appended to the source of custom effects, which only have to provide `fn effect(uv: vec2<f32>) -> vec4<f32>`.
+// post_common.wgsl is included before it, other shader files can be pulled in with #include.
+const CUSTOM_EFFECT_ENTRY: &str = "
+@fragment
+fn fragment(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    return effect(vertex.uv);
+}";
+
+// A fullscreen pass run on the finished 2D frame, effects are applied in the order they are in Pipeline2D::post_effects.
+#[derive(Clone)]
+pub enum PostEffect2D {
+    GaussianBlur {
+        radius: f32, // Pixels
+    },
+    ColorGrade {
+        brightness: f32, // Added to every channel, 0 leaves the frame alone
+        contrast: f32, // 1 leaves the frame alone
+        saturation: f32, // 0 is greyscale, 1 leaves the frame alone
+        tint: Color,
+    },
+    Vignette {
+        intensity: f32,
+        radius: f32, // Distance from the centre where the fade ends, 1 is the corners
+        softness: f32,
+        color: Color,
+    },
+    // WGSL with access to source_texture, source_sampler and the post uniform (params, texel), see post_common.wgsl.
+    Custom {
+        source: String,
+        params: [f32; 8],
+    },
+}
+
+// Which fragment shader a pass uses.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum PostShader2D {
+    Copy,
+    Blur,
+    ColorGrade,
+    Vignette,
+    Custom(String),
+    EncodeSrgb, // Only written to the frame, in its format
+}
+
+impl PostShader2D {
+    fn entry_point(&self) -> &'static str {
+        return match self {
+            PostShader2D::Copy => "copy",
+            PostShader2D::Blur => "blur",
+            PostShader2D::ColorGrade => "color_grade",
+            PostShader2D::Vignette => "vignette",
+            PostShader2D::Custom(_) => "fragment",
+            PostShader2D::EncodeSrgb => "encode_srgb",
+        };
+    }
+}
+
+// Where a pass writes to, one of the processor's own textures or the frame.
+pub enum PostDestination2D<'a> {
+    Target(usize),
+    View(&'a TextureView),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct PostUniform2D {
+    params: [[f32; 4]; 2],
+    texel: [f32; 2],
+    direction: [f32; 2],
+}
+
+impl PostUniform2D {
+    // The texel size is filled in by the pass.
+    fn new(params: [[f32; 4]; 2]) -> PostUniform2D {
+        return PostUniform2D {
+            params,
+            texel: [0.0, 0.0],
+            direction: [0.0, 0.0],
+        }
+    }
+}
+
+struct PostTarget2D {
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+// Owns the intermediate textures the frame is rendered into when post-processing or backdrop blur is used.
+// Target 0 holds the scene, 1 is the other half of the ping-pong, 2 is used between blur passes and 3 holds blurred backdrops.
+pub struct PostProcessor2D {
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<PostShader2D, RenderPipeline>,
+    custom_modules: HashMap<String, ShaderModule>,
+    uniform_layout: BindGroupLayout,
+    uniform_buffer: Buffer,
+    uniform_bind_group: BindGroup,
+    uniforms: Vec<u8>, // Written at the end of the frame, everything is recorded by then
+    sampler: Sampler,
+    targets: Vec<PostTarget2D>,
+    size: (u32, u32),
+    format: TextureFormat,
+    encode_format: Option<TextureFormat>, // The frame's format when the last pass has to encode to sRGB itself
+}
+
+pub const SCENE_TARGET: usize = 0;
+pub const BACKDROP_TARGET: usize = 3;
+const PING_TARGET: usize = 1;
+const BLUR_TARGET: usize = 2;
+
+impl PostProcessor2D {
+    pub fn new(r_state: &WgpuState, texture_layout: &BindGroupLayout) -> PostProcessor2D {
+        let uniform_layout = r_state.gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("2D Post Uniform Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<PostUniform2D>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = r_state.gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("2D Post Pipeline Layout"),
+            bind_group_layouts: &[texture_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = r_state.gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("2D Post Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let (uniform_buffer, uniform_bind_group) = Self::create_uniforms(&r_state.gpu.device, &uniform_layout, 16);
+
+        return PostProcessor2D {
+            pipeline_layout,
+            pipelines: HashMap::new(),
+            custom_modules: HashMap::new(),
+            uniform_layout,
+            uniform_buffer,
+            uniform_bind_group,
+            uniforms: vec![],
+            sampler,
+            targets: vec![],
+            size: (0, 0),
+            format: r_state.render_format,
+            encode_format: if r_state.encode_srgb { Some(r_state.swapchain_format) } else { None },
+        };
+    }
+
+    fn create_uniforms(device: &Device, layout: &BindGroupLayout, slots: u64) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("2D Post Uniforms"),
+            size: slots * UNIFORM_SLOT,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("2D Post Uniforms"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<PostUniform2D>() as u64),
+                }),
+            }],
+        });
+        return (buffer, bind_group);
+    }
+
+    // Gets textures, pipelines and uniform space ready for a frame. Backdrops are the number of backdrop blurs in the frame.
+    pub fn begin_frame(&mut self, r_state: &WgpuState, texture_layout: &BindGroupLayout, effects: &[PostEffect2D], backdrops: usize) {
+        let (width, height) = (r_state.config.width, r_state.config.height);
+        if self.targets.is_empty() || self.size != (width, height) {
+            self.size = (width, height);
+            self.targets = (0..4).map(|_| self.create_target(&r_state.gpu.device, texture_layout)).collect();
+        }
+
+        let mut shaders = vec![PostShader2D::Copy, PostShader2D::Blur];
+        if self.encode_format.is_some() {
+            shaders.push(PostShader2D::EncodeSrgb);
+        }
+        let mut passes = backdrops as u64 * 2 + 2;
+        for effect in effects {
+            shaders.push(Self::shader_for(effect));
+            passes += if let PostEffect2D::GaussianBlur { .. } = effect { 2 } else { 1 };
+        }
+        for shader in shaders {
+            if !self.pipelines.contains_key(&shader) {
+                let pipeline = self.create_pipeline(r_state, &shader);
+                self.pipelines.insert(shader, pipeline);
+            }
+        }
+
+        // The buffer can only be replaced before any pass has been recorded with it.
+        if passes * UNIFORM_SLOT > self.uniform_buffer.size() {
+            let (buffer, bind_group) = Self::create_uniforms(&r_state.gpu.device, &self.uniform_layout, passes.next_power_of_two());
+            self.uniform_buffer = buffer;
+            self.uniform_bind_group = bind_group;
+        }
+        self.uniforms.clear();
+    }
+
+    // Everything is built again from the current shader modules the next time it's used.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+        self.custom_modules.clear();
+    }
+
+    pub fn target_view(&self, target: usize) -> &TextureView {
+        return &self.targets[target].view;
+    }
+
+    pub fn target_bind_group(&self, target: usize) -> &BindGroup {
+        return &self.targets[target].bind_group;
+    }
+
+    // Blurs the scene as it is right now into the backdrop target.
+    pub fn blur_backdrop(&mut self, encoder: &mut CommandEncoder, radius: f32, timer: Option<&GpuTimer2D>) {
+        self.blur(encoder, radius, SCENE_TARGET, PostDestination2D::Target(BACKDROP_TARGET), timer);
+    }
+
+    // Runs the effects on the scene target and writes the result to the frame.
+    // When the frame is encoded by hand the result stays in a target instead, which is returned so more can be
+    // drawn on top of the effects before encode writes it to the frame.
+    pub fn run_effects(&mut self, encoder: &mut CommandEncoder, effects: &[PostEffect2D], view: &TextureView, timer: Option<&GpuTimer2D>) -> Option<usize> {
+        let encode = self.encode_format.is_some();
+        if effects.is_empty() {
+            if encode {
+                return Some(SCENE_TARGET);
+            }
+            self.pass(encoder, &PostShader2D::Copy, SCENE_TARGET, PostDestination2D::View(view), PostUniform2D::new([[0.0; 4]; 2]), timer);
+            return None;
+        }
+        let mut source = SCENE_TARGET;
+        for (i, effect) in effects.iter().enumerate() {
+            let other = if source == SCENE_TARGET { PING_TARGET } else { SCENE_TARGET };
+            let destination = if i == effects.len() - 1 && !encode { PostDestination2D::View(view) } else { PostDestination2D::Target(other) };
+            match effect {
+                PostEffect2D::GaussianBlur { radius } => self.blur(encoder, *radius, source, destination, timer),
+                PostEffect2D::ColorGrade { brightness, contrast, saturation, tint } => {
+                    let params = [[*brightness, *contrast, *saturation, 0.0], tint.to_linear_array()];
+                    self.pass(encoder, &PostShader2D::ColorGrade, source, destination, PostUniform2D::new(params), timer);
+                }
+                PostEffect2D::Vignette { intensity, radius, softness, color } => {
+                    let params = [[*intensity, *radius, *softness, 0.0], color.to_linear_array()];
+                    self.pass(encoder, &PostShader2D::Vignette, source, destination, PostUniform2D::new(params), timer);
+                }
+                PostEffect2D::Custom { source: wgsl, params } => {
+                    let params = [[params[0], params[1], params[2], params[3]], [params[4], params[5], params[6], params[7]]];
+                    self.pass(encoder, &PostShader2D::Custom(wgsl.clone()), source, destination, PostUniform2D::new(params), timer);
+                }
+            }
+            source = other;
+        }
+        return if encode { Some(source) } else { None };
+    }
+
+    // Encodes the linear colors in the target to sRGB into the frame, for surfaces that can't do it on write.
+    pub fn encode(&mut self, encoder: &mut CommandEncoder, source: usize, view: &TextureView, timer: Option<&GpuTimer2D>) {
+        self.pass(encoder, &PostShader2D::EncodeSrgb, source, PostDestination2D::View(view), PostUniform2D::new([[0.0; 4]; 2]), timer);
+    }
+
+    // Every pass has its own uniform slot, so this is how many were recorded this frame.
+    pub fn get_pass_count(&self) -> u32 {
+        return (self.uniforms.len() as u64 / UNIFORM_SLOT) as u32;
+    }
+
+    // Uniform writes only reach the GPU at submit, so they're all written once every pass is recorded.
+    pub fn finish(&mut self, r_state: &WgpuState) {
+        if !self.uniforms.is_empty() {
+            r_state.gpu.queue.write_buffer(&self.uniform_buffer, 0, &self.uniforms);
+        }
+    }
+
+    // A radius of 0 or less leaves the image as it is, it's only copied.
+    fn blur(&mut self, encoder: &mut CommandEncoder, radius: f32, source: usize, destination: PostDestination2D, timer: Option<&GpuTimer2D>) {
+        let radius = radius.max(0.0);
+        if radius == 0.0 {
+            self.pass(encoder, &PostShader2D::Copy, source, destination, PostUniform2D::new([[0.0; 4]; 2]), timer);
+            return;
+        }
+        let params = [[radius, 0.0, 0.0, 0.0], [0.0; 4]];
+        self.pass(encoder, &PostShader2D::Blur, source, PostDestination2D::Target(BLUR_TARGET), PostUniform2D { direction: [1.0, 0.0], ..PostUniform2D::new(params) }, timer);
+        self.pass(encoder, &PostShader2D::Blur, BLUR_TARGET, destination, PostUniform2D { direction: [0.0, 1.0], ..PostUniform2D::new(params) }, timer);
+    }
+
+    fn pass(&mut self, encoder: &mut CommandEncoder, shader: &PostShader2D, source: usize, destination: PostDestination2D, uniform: PostUniform2D, timer: Option<&GpuTimer2D>) {
+        let offset = self.uniforms.len() as u32;
+        let uniform = PostUniform2D {
+            texel: [1.0 / self.size.0.max(1) as f32, 1.0 / self.size.1.max(1) as f32],
+            ..uniform
+        };
+        self.uniforms.extend_from_slice(bytemuck::bytes_of(&uniform));
+        self.uniforms.resize(self.uniforms.len() + UNIFORM_SLOT as usize - std::mem::size_of::<PostUniform2D>(), 0);
+
+        let view = match destination {
+            PostDestination2D::Target(target) => &self.targets[target].view,
+            PostDestination2D::View(view) => view,
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("2D Post Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: timer.and_then(|timer| timer.pass_writes()),
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipelines[shader]);
+        rpass.set_bind_group(0, &self.targets[source].bind_group, &[]);
+        rpass.set_bind_group(1, &self.uniform_bind_group, &[offset]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    fn shader_for(effect: &PostEffect2D) -> PostShader2D {
+        return match effect {
+            PostEffect2D::GaussianBlur { .. } => PostShader2D::Blur,
+            PostEffect2D::ColorGrade { .. } => PostShader2D::ColorGrade,
+            PostEffect2D::Vignette { .. } => PostShader2D::Vignette,
+            PostEffect2D::Custom { source, .. } => PostShader2D::Custom(source.clone()),
+        };
+    }
+
+    // A custom effect that doesn't compile logs why and copies the frame through unchanged.
+    fn create_pipeline(&mut self, r_state: &WgpuState, shader: &PostShader2D) -> RenderPipeline {
+        if let PostShader2D::Custom(source) = shader {
+            if !self.custom_modules.contains_key(source) {
+                let wgsl = format!("#include \"post_common.wgsl\"\n{}\n{}", source, CUSTOM_EFFECT_ENTRY);
+                match r_state.gpu.shader_modules.borrow().create_variant(&r_state.gpu.device, &wgsl, "2D Custom Post Effect", &[]) {
+                    Ok(module) => {
+                        self.custom_modules.insert(source.clone(), module);
+                    }
+                    Err(err) => log::error!("Custom post effect failed to compile:\n{}", err),
+                }
+            }
+        }
+        let (module, entry_point) = match shader {
+            PostShader2D::Custom(source) if self.custom_modules.contains_key(source) => (&self.custom_modules[source], shader.entry_point()),
+            PostShader2D::Custom(_) => (&r_state.gpu.shader_modules.borrow().post_shader, PostShader2D::Copy.entry_point()),
+            _ => (&r_state.gpu.shader_modules.borrow().post_shader, shader.entry_point()),
+        };
+        return r_state.gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("2D Post Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: if *shader == PostShader2D::EncodeSrgb { self.encode_format.unwrap_or(self.format) } else { self.format },
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    fn create_target(&self, device: &Device, texture_layout: &BindGroupLayout) -> PostTarget2D {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2D Post Target"),
+            size: wgpu::Extent3d {
+                width: self.size.0.max(1),
+                height: self.size.1.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("2D Post Target"),
+            layout: texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        return PostTarget2D {
+            view,
+            bind_group,
+        };
+    }
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::{cell::Cell, sync::{Arc, Mutex}, time::Duration};
+
+use wgpu::{Buffer, BufferUsages, CommandEncoder, Device, QuerySet, RenderPassTimestampWrites};
+
+use crate::renderer::WgpuState;
+
+// Timestamps go two per pass, passes past this many in a frame aren't timed.
+const MAX_TIMED_PASSES: u32 = 64;
+// Results come back a few frames late, frames that find every readback buffer busy aren't timed.
+const READBACK_BUFFERS: usize = 3;
+
+// What Pipeline2D did to draw the last frame.
+#[derive(Clone, Default, Debug)]
+pub struct RenderStats2D {
+    pub draw_nodes: usize, // Layer contents included, layers themselves not
+    pub draw_calls: u32, // Text is drawn by the glyph brush and isn't counted
+    pub vertices: usize,
+    pub indices: usize,
+    pub uploaded_bytes: u64, // Vertices, indices and view uniforms
+    pub layout_time: Duration, // Drawing the tree into nodes and turning them into batches
+    pub encode_time: Duration, // Recording the passes
+    pub gpu_time: Option<Duration>, // Sum of the timed passes of a recent frame, None without timestamp query support
+}
+
+impl RenderStats2D {
+    // The text of the debug overlay.
+    pub fn summary(&self) -> String {
+        let gpu = match self.gpu_time {
+            Some(time) => format!("{:.2} ms", time.as_secs_f64() * 1000.0),
+            None => "n/a".to_string(),
+        };
+        return format!(
+            "nodes {}  draw calls {}\nvertices {}  indices {}  uploaded {:.1} KiB\nlayout {:.2} ms  encode {:.2} ms  gpu {}",
+            self.draw_nodes,
+            self.draw_calls,
+            self.vertices,
+            self.indices,
+            self.uploaded_bytes as f64 / 1024.0,
+            self.layout_time.as_secs_f64() * 1000.0,
+            self.encode_time.as_secs_f64() * 1000.0,
+            gpu,
+        );
+    }
+}
+
+#[derive(PartialEq)]
+enum ReadbackState2D {
+    Idle,
+    Copied, // Resolved into the buffer by a frame that hasn't necessarily been submitted yet
+    Mapping,
+}
+
+struct TimerReadback2D {
+    buffer: Buffer,
+    state: ReadbackState2D,
+    mapped: Arc<Mutex<Option<bool>>>, // Whether mapping worked, None until it's done
+    queries: u32,
+}
+
+// Times render passes with timestamp queries. Passes ask for their timestamp writes, the frame resolves them all at the end.
+pub struct GpuTimer2D {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readbacks: Vec<TimerReadback2D>,
+    used: Cell<u32>, // Queries handed out this frame, passes only borrow the timer
+    period: f32, // Nanoseconds per timestamp tick
+    last_time: Option<Duration>,
+}
+
+impl GpuTimer2D {
+    // None when the device wasn't created with timestamp queries.
+    pub fn new(r_state: &WgpuState) -> Option<GpuTimer2D> {
+        if !r_state.gpu.device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let size = MAX_TIMED_PASSES as u64 * 2 * 8;
+        let query_set = r_state.gpu.device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("2D Pass Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_TIMED_PASSES * 2,
+        });
+        let resolve_buffer = r_state.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("2D Timestamp Resolve"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_BUFFERS).map(|_| TimerReadback2D {
+            buffer: r_state.gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("2D Timestamp Readback"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            state: ReadbackState2D::Idle,
+            mapped: Arc::new(Mutex::new(None)),
+            queries: 0,
+        }).collect();
+        return Some(GpuTimer2D {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            used: Cell::new(0),
+            period: r_state.gpu.queue.get_timestamp_period(),
+            last_time: None,
+        });
+    }
+
+    // Picks up results of earlier frames, which have all been submitted by the time the next one starts.
+    pub fn begin_frame(&mut self, device: &Device) {
+        for readback in &mut self.readbacks {
+            if readback.state == ReadbackState2D::Copied {
+                let mapped = readback.mapped.clone();
+                readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    *mapped.lock().expect("Timer readback lock") = Some(result.is_ok());
+                });
+                readback.state = ReadbackState2D::Mapping;
+            }
+        }
+        device.poll(wgpu::Maintain::Poll);
+        for readback in &mut self.readbacks {
+            if readback.state != ReadbackState2D::Mapping {
+                continue;
+            }
+            match readback.mapped.lock().expect("Timer readback lock").take() {
+                Some(true) => {}
+                Some(false) => {
+                    // The buffer isn't mapped, so it can take the next frame's timestamps right away.
+                    readback.state = ReadbackState2D::Idle;
+                    continue;
+                }
+                None => continue,
+            }
+            {
+                let data = readback.buffer.slice(..readback.queries as u64 * 8).get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                let ticks: u64 = timestamps.chunks_exact(2).map(|pair| pair[1].saturating_sub(pair[0])).sum();
+                self.last_time = Some(Duration::from_nanos((ticks as f64 * self.period as f64) as u64));
+            }
+            readback.buffer.unmap();
+            readback.state = ReadbackState2D::Idle;
+        }
+        self.used.set(0);
+    }
+
+    // Timestamp writes for the next pass, None once the query set is full.
+    pub fn pass_writes(&self) -> Option<RenderPassTimestampWrites<'_>> {
+        let used = self.used.get();
+        if used + 2 > MAX_TIMED_PASSES * 2 {
+            return None;
+        }
+        self.used.set(used + 2);
+        return Some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(used),
+            end_of_pass_write_index: Some(used + 1),
+        });
+    }
+
+    // Records copying this frame's timestamps somewhere they can be read back from.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        let used = self.used.get();
+        if used == 0 {
+            return;
+        }
+        let readback = match self.readbacks.iter_mut().find(|readback| readback.state == ReadbackState2D::Idle) {
+            Some(readback) => readback,
+            None => return,
+        };
+        encoder.resolve_query_set(&self.query_set, 0..used, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &readback.buffer, 0, used as u64 * 8);
+        readback.state = ReadbackState2D::Copied;
+        readback.queries = used;
+    }
+
+    pub fn get_last_time(&self) -> Option<Duration> {
+        return self.last_time;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,371 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{math::color::Color, renderer::texture_atlas::AtlasImageId};
+
+use super::{drawable_2d::Drawable2D, drawable_state_2d::{DrawableState2D, DrawNode2D, ImageDrawNode2D, NodeInfo2D}};
+
+// An atlas image cut up into frames, frame rects are normalized as [x, y, width, height].
+pub struct SpriteSheet {
+    image: AtlasImageId,
+    frames: Vec<[f32; 4]>,
+}
+
+impl SpriteSheet {
+    // Cuts the image into equally sized cells, read left to right, top to bottom.
+    pub fn from_grid(image: AtlasImageId, columns: u32, rows: u32) -> SpriteSheet {
+        let mut frames = vec![];
+        let cell_width = 1.0 / columns.max(1) as f32;
+        let cell_height = 1.0 / rows.max(1) as f32;
+        for row in 0..rows {
+            for column in 0..columns {
+                frames.push([column as f32 * cell_width, row as f32 * cell_height, cell_width, cell_height]);
+            }
+        }
+        return SpriteSheet {
+            image,
+            frames,
+        }
+    }
+
+    // Uses explicit frame rects given in pixels of the source image, as [x, y, width, height].
+    pub fn from_frames(image: AtlasImageId, image_width: u32, image_height: u32, frames: &[[u32; 4]]) -> SpriteSheet {
+        let width = image_width as f32;
+        let height = image_height as f32;
+        return SpriteSheet {
+            image,
+            frames: frames.iter()
+                .map(|f| [f[0] as f32 / width, f[1] as f32 / height, f[2] as f32 / width, f[3] as f32 / height])
+                .collect(),
+        }
+    }
+
+    pub fn get_image(&self) -> AtlasImageId {
+        return self.image;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        return self.frames.len();
+    }
+
+    pub fn get_frame(&self, frame: usize) -> Option<[f32; 4]> {
+        return self.frames.get(frame).copied();
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LoopMode {
+    Once,      // Stops on the last frame
+    Loop,      // Starts over from the first frame
+    PingPong,  // Plays backwards again once it reaches the end
+}
+
+pub struct SpriteAnimation {
+    frames: Vec<(usize, f32)>, // Sheet frame index and how long it's shown for, in seconds
+    loop_mode: LoopMode,
+}
+
+impl SpriteAnimation {
+    pub fn new(frames: &[usize], frame_duration: f32, loop_mode: LoopMode) -> SpriteAnimation {
+        return SpriteAnimation {
+            frames: frames.iter().map(|f| (*f, frame_duration)).collect(),
+            loop_mode,
+        }
+    }
+
+    pub fn with_durations(frames: &[(usize, f32)], loop_mode: LoopMode) -> SpriteAnimation {
+        return SpriteAnimation {
+            frames: frames.to_vec(),
+            loop_mode,
+        }
+    }
+}
+
+struct SpritePlayback {
+    animation: String,
+    position: usize, // Index into the animation's frames, not the sheet's
+    elapsed: f32,
+    reversing: bool,
+    playing: bool,
+    finished: bool,
+}
+
+pub struct Sprite2D {
+    state: DrawableState2D,
+    sheet: Rc<SpriteSheet>,
+    animations: HashMap<String, SpriteAnimation>,
+    playback: Option<SpritePlayback>,
+    frame: usize, // Sheet frame that is drawn
+    speed: f32,
+    color: Color,
+}
+
+impl Sprite2D {
+    pub fn new(sheet: Rc<SpriteSheet>) -> Sprite2D {
+        return Sprite2D {
+            state: DrawableState2D::new(),
+            sheet,
+            animations: HashMap::new(),
+            playback: None,
+            frame: 0,
+            speed: 1.0,
+            color: Color::new(255, 255, 255, 255),
+        }
+    }
+
+    pub fn add_animation(&mut self, name: &str, animation: SpriteAnimation) -> &mut Self {
+        self.animations.insert(name.to_string(), animation);
+        return self;
+    }
+
+    // Starts the animation from its first frame, returns false if there is no animation with that name.
+    pub fn play(&mut self, name: &str) -> bool {
+        let animation = match self.animations.get(name) {
+            Some(animation) if !animation.frames.is_empty() => animation,
+            _ => return false,
+        };
+        self.frame = animation.frames[0].0;
+        self.playback = Some(SpritePlayback {
+            animation: name.to_string(),
+            position: 0,
+            elapsed: 0.0,
+            reversing: false,
+            playing: true,
+            finished: false,
+        });
+        self.state.set_dirty(true);
+        return true;
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            playback.playing = false;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            playback.playing = !playback.finished;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.playback = None;
+        self.state.set_dirty(true);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        return self.playback.as_ref().is_some_and(|p| p.playing);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.playback.as_ref().is_some_and(|p| p.finished);
+    }
+
+    pub fn get_animation(&self) -> Option<&str> {
+        return self.playback.as_ref().map(|p| p.animation.as_str());
+    }
+
+    pub fn set_speed(&mut self, speed: f32) -> &mut Self {
+        self.speed = speed;
+        return self;
+    }
+
+    // Shows a single frame of the sheet, this stops any animation.
+    pub fn set_frame(&mut self, frame: usize) -> &mut Self {
+        self.playback = None;
+        self.frame = frame;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    pub fn set_color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self.state.set_dirty(true);
+        return self;
+    }
+
+    fn advance(&mut self, delta: f32) {
+        let playback = match &mut self.playback {
+            Some(playback) if playback.playing => playback,
+            _ => return,
+        };
+        let animation = match self.animations.get(&playback.animation) {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        playback.elapsed += delta * self.speed;
+        let last = animation.frames.len() - 1;
+        // Frames with no duration would never let the loop finish, so they count as one update each.
+        while playback.elapsed >= animation.frames[playback.position].1 {
+            playback.elapsed -= animation.frames[playback.position].1.max(0.0);
+            let at_end = if playback.reversing { playback.position == 0 } else { playback.position == last };
+            if at_end {
+                match animation.loop_mode {
+                    LoopMode::Once => {
+                        playback.playing = false;
+                        playback.finished = true;
+                        playback.elapsed = 0.0;
+                        break;
+                    }
+                    LoopMode::Loop => playback.position = 0,
+                    LoopMode::PingPong => {
+                        playback.reversing = !playback.reversing;
+                        if last > 0 {
+                            playback.position = if playback.reversing { last - 1 } else { 1 };
+                        }
+                    }
+                }
+            } else if playback.reversing {
+                playback.position -= 1;
+            } else {
+                playback.position += 1;
+            }
+            if animation.frames[playback.position].1 <= 0.0 {
+                playback.elapsed = 0.0;
+                break;
+            }
+        }
+
+        let frame = animation.frames[playback.position].0;
+        if frame != self.frame {
+            self.frame = frame;
+            self.state.set_dirty(true);
+        }
+    }
+}
+
+impl Drawable2D for Sprite2D {
+    fn get_state(&mut self) -> &mut DrawableState2D {
+        return &mut self.state;
+    }
+
+    fn update(&mut self, delta: f32) {
+        self.advance(delta);
+        // A loop that waits until dirty stops ticking once nothing is, so a playing animation keeps the sprite dirty.
+        if self.is_playing() {
+            self.state.set_dirty(true);
+        }
+        for child in self.get_state().get_children() {
+            child.update(delta);
+        }
+    }
+
+    fn draw(&mut self) -> Option<Vec<DrawNode2D>> {
+        let uv_rect = self.sheet.get_frame(self.frame)?;
+        let node = DrawNode2D::Image(ImageDrawNode2D {
+            info: NodeInfo2D::new(self.state.get_id()),
+            transform: *self.state.get_transform_state(),
+            image: self.sheet.get_image(),
+            uv_rect,
+            color: self.color.to_linear_vec4(),
+            corner_radius: 0.0,
+        });
+        return Some(vec![node]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::texture_atlas::AtlasImageId;
+
+    use super::*;
+
+    // A sprite playing the animation on a sheet of four frames.
+    fn playing(animation: SpriteAnimation) -> Sprite2D {
+        let mut sprite = Sprite2D::new(Rc::new(SpriteSheet::from_grid(AtlasImageId::dummy(), 4, 1)));
+        sprite.add_animation("test", animation);
+        assert!(sprite.play("test"));
+        return sprite;
+    }
+
+    // The sheet frame shown after each update.
+    fn frames_after(sprite: &mut Sprite2D, deltas: &[f32]) -> Vec<usize> {
+        return deltas.iter().map(|delta| {
+            sprite.advance(*delta);
+            sprite.frame
+        }).collect();
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Once));
+        assert_eq!(frames_after(&mut sprite, &[0.25; 4]), vec![1, 2, 2, 2]);
+        assert!(sprite.is_finished());
+        assert!(!sprite.is_playing());
+        sprite.resume();
+        assert!(!sprite.is_playing());
+    }
+
+    #[test]
+    fn loop_starts_over_from_the_first_frame() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Loop));
+        assert_eq!(frames_after(&mut sprite, &[0.25; 4]), vec![1, 2, 0, 1]);
+        assert!(sprite.is_playing());
+    }
+
+    #[test]
+    fn ping_pong_turns_around_at_both_ends() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::PingPong));
+        assert_eq!(frames_after(&mut sprite, &[0.25; 6]), vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn ping_pong_with_one_frame_stays_on_it() {
+        let mut sprite = playing(SpriteAnimation::new(&[3], 0.25, LoopMode::PingPong));
+        assert_eq!(frames_after(&mut sprite, &[0.25; 3]), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn short_updates_add_up() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1], 0.25, LoopMode::Loop));
+        assert_eq!(frames_after(&mut sprite, &[0.125; 4]), vec![0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn zero_duration_frames_advance_once_per_update() {
+        let mut sprite = playing(SpriteAnimation::with_durations(&[(0, 0.0), (1, 0.0), (2, 0.0)], LoopMode::Loop));
+        assert_eq!(frames_after(&mut sprite, &[1.0, 0.0, 1.0]), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn a_large_delta_skips_ahead() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Loop));
+        // Ten frames and a bit, the bit carries over to the next update.
+        assert_eq!(frames_after(&mut sprite, &[2.625, 0.125]), vec![1, 2]);
+
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Once));
+        assert_eq!(frames_after(&mut sprite, &[100.0]), vec![2]);
+        assert!(sprite.is_finished());
+    }
+
+    #[test]
+    fn speed_scales_the_delta() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Loop));
+        sprite.set_speed(2.0);
+        assert_eq!(frames_after(&mut sprite, &[0.125, 0.125]), vec![1, 2]);
+    }
+
+    #[test]
+    fn a_playing_sprite_stays_dirty_between_frame_changes() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1], 1.0, LoopMode::Once));
+        sprite.clear_dirty();
+        sprite.update(0.25);
+        assert!(sprite.is_dirty());
+        sprite.clear_dirty();
+        sprite.update(2.0);
+        assert!(sprite.is_finished());
+        sprite.clear_dirty();
+        sprite.update(0.25);
+        assert!(!sprite.is_dirty());
+    }
+
+    #[test]
+    fn paused_sprites_keep_their_frame() {
+        let mut sprite = playing(SpriteAnimation::new(&[0, 1, 2], 0.25, LoopMode::Loop));
+        sprite.pause();
+        assert_eq!(frames_after(&mut sprite, &[1.0]), vec![0]);
+        sprite.resume();
+        assert_eq!(frames_after(&mut sprite, &[0.25]), vec![1]);
+    }
+}
\ No newline at end of file
@@ -2,6 +2,8 @@ use crate::math::color::Color;
 
 use super::{drawable_2d::Drawable2D, drawable_state_2d::DrawableState2D};
 
+// Not used anywhere yet.
+#[allow(dead_code)]
 struct Text2D {
     state: DrawableState2D,
     text: String,
@@ -9,6 +11,7 @@ struct Text2D {
     scale: f32,
 }
 
+#[allow(dead_code)]
 impl Text2D {
     pub fn set_text(&mut self, text: &str) {
         self.text = text.to_string();
@@ -16,7 +19,7 @@ impl Text2D {
     }
 
     pub fn set_color(&mut self, color: &Color) {
-        self.color = color.clone();
+        self.color = *color;
         self.set_dirty(true);
     }
 
@@ -1,64 +1,37 @@
-use std::{vec, ptr::null};
-use bytemuck::NoUninit;
-use wgpu::{Limits, DepthStencilState, SurfaceConfiguration, Surface, Device, RenderPipeline, Queue, ShaderModule, PipelineLayout, TextureFormat, ColorTargetState, util::{DeviceExt, BufferInitDescriptor}, VertexBufferLayout, Buffer, BindGroupLayout, BindGroup, BufferUsages, RenderPass, CommandEncoder, SurfaceTexture, TextureView};
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::{WindowBuilder, Window}, dpi::{PhysicalSize, Size},
-};
-use rustyfun::{Game, renderer::{WgpuState, DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT}};
+#![allow(clippy::needless_return)]
 
-fn main() {
-    unsafe {
-        pollster::block_on(run());
-    }
-}
+use rustyfun::{App, AppContext, core_2d::{drawable_2d::{Drawable2D, SimpleDrawable2D}, box_2d::Box2D, drawable_state_2d::Anchor}, math::color::Color};
 
-async unsafe fn run() {
-    // Set everything up so that we can create a WgpuState
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().with_inner_size(PhysicalSize::new(DEFAULT_SCREEN_WIDTH, DEFAULT_SCREEN_HEIGHT)).build(&event_loop).unwrap();
+// Two boxes in a container, one hanging off its top left corner.
+struct Demo;
+
+impl App for Demo {
+    fn init(ctx: &mut AppContext) -> Demo {
+        let mut ui_container = SimpleDrawable2D::new();
+        ui_container.set_abs_pos(100.0, 100.0);
+        ui_container.set_abs_size(500.0, 500.0);
+        let mut test = Box2D::new();
+        //test.set_rel_size(0.5, 0.5);
+        test.set_abs_size(300.0, 300.0);
+        test.set_color(Color::new(186, 26, 66, 255));
+        ui_container.add_child(Box::new(test));
 
-    let mut size = window.inner_size();
-    size.width = size.width.min(1);
-    size.height = size.height.max(1);
+        let mut test2 = Box2D::new();
+        test2.set_abs_size(300.0, 300.0);
+        //test2.set_rel_pos(0.5, 0.5);
+        test2.set_alignment(Anchor::TOP_LEFT);
+        test2.set_origin(Anchor::BOTTOM_RIGHT);
+        test2.set_color(Color::new(12, 150, 67, 255));
+        ui_container.add_child(Box::new(test2));
 
-    let mut r_state = pollster::block_on(WgpuState::new(window));
-    
-    //WgpuState is created, initialize the game.
-    let mut game = Game::prepare(&mut r_state);
+        ctx.root.add_child(Box::new(ui_container));
+        return Demo;
+    }
+}
 
-    //Run the game...
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
-    
-        match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(new_size) => {
-                    game.on_resized(&mut r_state, new_size);
-                }
-                _ => (),
-            },
-            Event::MainEventsCleared => {
-                r_state.window.request_redraw();
-            },
-            Event::RedrawRequested(_) => {
-                let current_frame = r_state.surface
-                    .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
-                let view = current_frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut current_encoder = r_state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: None,
-                });
-                game.draw(&mut r_state, &mut current_encoder, &view);
-                r_state.queue.submit(Some(current_encoder.finish()));
-                current_frame.present();
-            }
-            _ => (),
-        }
-    });
+fn main() {
+    env_logger::init();
+    if let Err(err) = rustyfun::run::<Demo>() {
+        log::error!("{}", err);
+    }
 }
\ No newline at end of file
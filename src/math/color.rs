@@ -45,4 +45,101 @@ impl Color {
     pub fn to_array(&mut self) -> [f32; 4] {
         return [self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0, self.a as f32 / 255.0];
     }
+
+    // The channels are sRGB like design tools use, the renderer blends and interpolates in linear space.
+    // Alpha is coverage and stays as it is.
+    pub fn to_linear_array(&self) -> [f32; 4] {
+        return [srgb_to_linear(self.r as f32 / 255.0), srgb_to_linear(self.g as f32 / 255.0), srgb_to_linear(self.b as f32 / 255.0), self.a as f32 / 255.0];
+    }
+
+    pub fn to_linear_vec4(&self) -> Vector4<f32> {
+        let [r, g, b, a] = self.to_linear_array();
+        return Vector4::new(r, g, b, a);
+    }
+
+    // Rounds linear channels from 0 to 1 back to the nearest sRGB color.
+    pub fn from_linear(r: f32, g: f32, b: f32, a: f32) -> Color {
+        let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        return Color::new(to_u8(linear_to_srgb(r)), to_u8(linear_to_srgb(g)), to_u8(linear_to_srgb(b)), to_u8(a));
+    }
+
+    // Mixes in linear space, which is how light mixes, so the midpoint of two colors doesn't come out too dark.
+    pub fn lerp(&self, other: &Color, t: f32) -> Color {
+        let from = self.to_linear_array();
+        let to = other.to_linear_array();
+        let mix = |i: usize| from[i] + (to[i] - from[i]) * t;
+        return Color::from_linear(mix(0), mix(1), mix(2), mix(3));
+    }
+}
+
+// The sRGB transfer function, both sides from 0 to 1.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+    return ((value + 0.055) / 1.055).powf(2.4);
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        return value * 12.92;
+    }
+    return 1.055 * value.powf(1.0 / 2.4) - 0.055;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-5, "{} isn't {}", value, expected);
+    }
+
+    #[test]
+    fn srgb_to_linear_known_values() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert_close(srgb_to_linear(0.04045), 0.0031308);
+        assert_close(srgb_to_linear(0.5), 0.21404);
+        assert_close(srgb_to_linear(1.0), 1.0);
+    }
+
+    #[test]
+    fn linear_to_srgb_known_values() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert_close(linear_to_srgb(0.0031308), 0.04045);
+        assert_close(linear_to_srgb(0.21404), 0.5);
+        assert_close(linear_to_srgb(1.0), 1.0);
+    }
+
+    #[test]
+    fn both_sides_of_the_threshold_meet() {
+        assert_close(srgb_to_linear(0.04045), srgb_to_linear(0.04045 + 1e-6));
+        assert_close(linear_to_srgb(0.0031308), linear_to_srgb(0.0031308 + 1e-7));
+    }
+
+    #[test]
+    fn every_channel_value_survives_a_round_trip() {
+        for value in 0..=255u8 {
+            let color = Color::new(value, value, value, value);
+            let [r, g, b, a] = color.to_linear_array();
+            let back = Color::from_linear(r, g, b, a);
+            assert_eq!((back.r, back.g, back.b, back.a), (value, value, value, value));
+        }
+    }
+
+    #[test]
+    fn alpha_stays_linear() {
+        assert_close(Color::new(0, 0, 0, 128).to_linear_array()[3], 128.0 / 255.0);
+    }
+
+    #[test]
+    fn lerp_mixes_in_linear_space() {
+        let black = Color::new(0, 0, 0, 255);
+        let white = Color::new(255, 255, 255, 255);
+        let middle = black.lerp(&white, 0.5);
+        assert_eq!((middle.r, middle.g, middle.b, middle.a), (188, 188, 188, 255));
+        let start = black.lerp(&white, 0.0);
+        let end = black.lerp(&white, 1.0);
+        assert_eq!((start.r, end.r), (0, 255));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,567 @@
+use std::f32::consts::PI;
+
+use cgmath::{Vector2, InnerSpace};
+
+// How far a flattened curve is allowed to stray from the real one, in pixels.
+pub const DEFAULT_TOLERANCE: f32 = 0.25;
+// Miters longer than this many half widths fall back to a bevel.
+const MITER_LIMIT: f32 = 4.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PathCommand2D {
+    MoveTo(Vector2<f32>),
+    LineTo(Vector2<f32>),
+    QuadTo(Vector2<f32>, Vector2<f32>), // Control point, end point
+    CubicTo(Vector2<f32>, Vector2<f32>, Vector2<f32>), // Two control points, end point
+    Arc { center: Vector2<f32>, radius: f32, start_angle: f32, end_angle: f32 }, // Angles in radians, clockwise on screen
+    Close,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineJoin {
+    Miter,
+    Bevel,
+    Round,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct StrokeOptions {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub dashes: Vec<f32>, // Alternating dash and gap lengths, empty for a solid line
+    pub dash_offset: f32,
+}
+
+impl StrokeOptions {
+    pub fn new(width: f32) -> StrokeOptions {
+        return StrokeOptions {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+}
+
+// A flattened piece of a path.
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub points: Vec<Vector2<f32>>,
+    pub closed: bool,
+}
+
+// Triangle list output of the tessellator.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh2D {
+    pub vertices: Vec<Vector2<f32>>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh2D {
+    fn push_triangle(&mut self, a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&[a, b, c]);
+        self.indices.extend_from_slice(&[first, first + 1, first + 2]);
+    }
+
+    fn push_quad(&mut self, a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>, d: Vector2<f32>) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&[a, b, c, d]);
+        self.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+    }
+
+    // Triangles from the centre to every pair of neighbouring points.
+    fn push_fan(&mut self, centre: Vector2<f32>, points: &[Vector2<f32>]) {
+        for pair in points.windows(2) {
+            self.push_triangle(centre, pair[0], pair[1]);
+        }
+    }
+}
+
+// Turns the commands into polylines, curves and arcs are split into line segments.
+pub fn flatten(commands: &[PathCommand2D], tolerance: f32) -> Vec<Contour> {
+    let mut contours: Vec<Contour> = vec![];
+    let mut current: Vec<Vector2<f32>> = vec![];
+    let tolerance = tolerance.max(0.01);
+
+    let finish = |contours: &mut Vec<Contour>, current: &mut Vec<Vector2<f32>>, closed: bool| {
+        if current.len() > 1 {
+            contours.push(Contour {
+                points: std::mem::take(current),
+                closed,
+            });
+        } else {
+            current.clear();
+        }
+    };
+
+    for command in commands {
+        match *command {
+            PathCommand2D::MoveTo(point) => {
+                finish(&mut contours, &mut current, false);
+                current.push(point);
+            }
+            PathCommand2D::LineTo(point) => {
+                current.push(point);
+            }
+            PathCommand2D::QuadTo(control, end) => {
+                let start = *current.last().unwrap_or(&control);
+                let deviation = (start - control * 2.0 + end).magnitude();
+                let segments = ((deviation / (8.0 * tolerance)).sqrt().ceil() as usize).clamp(1, 128);
+                for i in 1..=segments {
+                    let t = i as f32 / segments as f32;
+                    let mt = 1.0 - t;
+                    current.push(start * (mt * mt) + control * (2.0 * mt * t) + end * (t * t));
+                }
+            }
+            PathCommand2D::CubicTo(control_1, control_2, end) => {
+                let start = *current.last().unwrap_or(&control_1);
+                let deviation = (start - control_1 * 2.0 + control_2).magnitude().max((control_1 - control_2 * 2.0 + end).magnitude());
+                let segments = ((deviation * 3.0 / (4.0 * tolerance)).sqrt().ceil() as usize).clamp(1, 128);
+                for i in 1..=segments {
+                    let t = i as f32 / segments as f32;
+                    let mt = 1.0 - t;
+                    current.push(start * (mt * mt * mt) + control_1 * (3.0 * mt * mt * t) + control_2 * (3.0 * mt * t * t) + end * (t * t * t));
+                }
+            }
+            PathCommand2D::Arc { center, radius, start_angle, end_angle } => {
+                let sweep = end_angle - start_angle;
+                let step = if radius > tolerance { 2.0 * (1.0 - tolerance / radius).acos() } else { PI / 2.0 };
+                let segments = ((sweep.abs() / step).ceil() as usize).clamp(1, 256);
+                for i in 0..=segments {
+                    let angle = start_angle + sweep * (i as f32 / segments as f32);
+                    current.push(center + Vector2::new(angle.cos(), angle.sin()) * radius);
+                }
+            }
+            PathCommand2D::Close => {
+                finish(&mut contours, &mut current, true);
+            }
+        }
+    }
+    finish(&mut contours, &mut current, false);
+
+    for contour in &mut contours {
+        contour.points.dedup_by(|a, b| (*a - *b).magnitude2() < 1e-8);
+        if contour.closed && contour.points.len() > 1 && (contour.points[0] - contour.points[contour.points.len() - 1]).magnitude2() < 1e-8 {
+            contour.points.pop();
+        }
+    }
+    return contours;
+}
+
+// Ear clips every contour on its own, contours with holes or self intersections aren't supported.
+pub fn fill(contours: &[Contour]) -> Mesh2D {
+    let mut mesh = Mesh2D::default();
+    for contour in contours {
+        let points = &contour.points;
+        if points.len() < 3 {
+            continue;
+        }
+        let first = mesh.vertices.len() as u32;
+        mesh.vertices.extend_from_slice(points);
+
+        let mut remaining: Vec<usize> = (0..points.len()).collect();
+        // Make the winding consistent so convex corners always have a positive cross product.
+        if signed_area(points) < 0.0 {
+            remaining.reverse();
+        }
+        while remaining.len() > 3 {
+            let count = remaining.len();
+            let mut clipped = false;
+            for i in 0..count {
+                let prev = remaining[(i + count - 1) % count];
+                let curr = remaining[i];
+                let next = remaining[(i + 1) % count];
+                if is_ear(points, &remaining, prev, curr, next) {
+                    mesh.indices.extend_from_slice(&[first + prev as u32, first + curr as u32, first + next as u32]);
+                    remaining.remove(i);
+                    clipped = true;
+                    break;
+                }
+            }
+            // Degenerate polygons may have no ears left, give up on them after a pass without progress.
+            if !clipped {
+                break;
+            }
+        }
+        if remaining.len() == 3 {
+            mesh.indices.extend_from_slice(&[first + remaining[0] as u32, first + remaining[1] as u32, first + remaining[2] as u32]);
+        }
+    }
+    return mesh;
+}
+
+pub fn stroke(contours: &[Contour], options: &StrokeOptions) -> Mesh2D {
+    let mut mesh = Mesh2D::default();
+    if options.width <= 0.0 {
+        return mesh;
+    }
+    // Like canvas, an odd dash list is repeated so dashes and gaps keep alternating.
+    let mut dashes = options.dashes.clone();
+    if dashes.len() % 2 == 1 {
+        dashes.extend_from_slice(&options.dashes);
+    }
+    for contour in contours {
+        if dashes.iter().any(|d| *d > 0.0) {
+            for dash in apply_dashes(contour, &dashes, options.dash_offset) {
+                stroke_polyline(&mut mesh, &dash, false, options);
+            }
+        } else {
+            stroke_polyline(&mut mesh, &contour.points, contour.closed, options);
+        }
+    }
+    return mesh;
+}
+
+fn stroke_polyline(mesh: &mut Mesh2D, points: &[Vector2<f32>], closed: bool, options: &StrokeOptions) {
+    if points.len() < 2 {
+        return;
+    }
+    let half_width = options.width / 2.0;
+    let segment_count = if closed { points.len() } else { points.len() - 1 };
+
+    // Joins between segments, closed contours also join the last segment to the first.
+    let mut inner_corners: Vec<Option<(Vector2<f32>, f32)>> = vec![None; points.len()];
+    let join_range = if closed { 0..points.len() } else { 1..points.len() - 1 };
+    for i in join_range {
+        let prev = points[(i + points.len() - 1) % points.len()];
+        let curr = points[i];
+        let next = points[(i + 1) % points.len()];
+        inner_corners[i] = add_join(mesh, prev, curr, next, half_width, options.join);
+    }
+
+    for i in 0..segment_count {
+        let mut start = points[i];
+        let mut end = points[(i + 1) % points.len()];
+        let direction = match (end - start).normalize() {
+            d if d.x.is_finite() && d.y.is_finite() => d,
+            _ => continue,
+        };
+        if !closed && options.cap == LineCap::Square {
+            if i == 0 {
+                start -= direction * half_width;
+            }
+            if i == segment_count - 1 {
+                end += direction * half_width;
+            }
+        }
+        let normal = Vector2::new(-direction.y, direction.x) * half_width;
+        // [positive side, negative side] at both ends, the inside of a join is moved onto the corner both segments share.
+        let mut start_sides = [start + normal, start - normal];
+        let mut end_sides = [end + normal, end - normal];
+        if let Some((corner, inner_side)) = inner_corners[i] {
+            start_sides[if inner_side > 0.0 { 0 } else { 1 }] = corner;
+        }
+        if let Some((corner, inner_side)) = inner_corners[(i + 1) % points.len()] {
+            end_sides[if inner_side > 0.0 { 0 } else { 1 }] = corner;
+        }
+        mesh.push_quad(start_sides[0], end_sides[0], end_sides[1], start_sides[1]);
+    }
+
+    if !closed && options.cap == LineCap::Round {
+        let last = points.len() - 1;
+        add_round_cap(mesh, points[0], points[0] - points[1], half_width);
+        add_round_cap(mesh, points[last], points[last] - points[last - 1], half_width);
+    }
+}
+
+// Fills the gap on the outside of the turn. When the segments are long enough their inner edges meet in a corner,
+// which is returned with the side of the segment normals it's on: the segments and the join all end there, so nothing
+// is covered twice and translucent strokes blend evenly. Segments shorter than that overlap on the inside of the turn.
+fn add_join(mesh: &mut Mesh2D, prev: Vector2<f32>, curr: Vector2<f32>, next: Vector2<f32>, half_width: f32, join: LineJoin) -> Option<(Vector2<f32>, f32)> {
+    let incoming = (curr - prev).normalize();
+    let outgoing = (next - curr).normalize();
+    let turn = cross(incoming, outgoing);
+    if !turn.is_finite() || turn.abs() < 1e-6 {
+        return None; // Straight or degenerate, the segment quads already meet
+    }
+    // The gap to fill is on the outside of the turn.
+    let side = if turn > 0.0 { -1.0 } else { 1.0 };
+    let normal_in = Vector2::new(-incoming.y, incoming.x) * half_width * side;
+    let normal_out = Vector2::new(-outgoing.y, outgoing.x) * half_width * side;
+    let a = curr + normal_in;
+    let b = curr + normal_out;
+    let bisector = (normal_in + normal_out).normalize();
+    let cos_half = bisector.dot(normal_in / half_width);
+
+    // The inner edges cross this far before and after the point, it has to fit into both segments next to the other joins.
+    let inner_reach = half_width * (1.0 - cos_half * cos_half).max(0.0).sqrt() / cos_half;
+    let shortest = (curr - prev).magnitude().min((next - curr).magnitude());
+    let inner_corner = if cos_half > 1e-3 && inner_reach <= shortest / 2.0 { Some((curr - bisector * (half_width / cos_half), -side)) } else { None };
+    let centre = inner_corner.map_or(curr, |(corner, _)| corner);
+
+    match join {
+        LineJoin::Bevel => mesh.push_triangle(centre, a, b),
+        LineJoin::Miter => {
+            if cos_half <= 1.0 / MITER_LIMIT {
+                mesh.push_triangle(centre, a, b);
+            } else {
+                let tip = curr + bisector * (half_width / cos_half);
+                mesh.push_quad(centre, a, tip, b);
+            }
+        }
+        LineJoin::Round => {
+            let start_angle = normal_in.y.atan2(normal_in.x);
+            let mut sweep = normal_out.y.atan2(normal_out.x) - start_angle;
+            if sweep > PI {
+                sweep -= 2.0 * PI;
+            } else if sweep < -PI {
+                sweep += 2.0 * PI;
+            }
+            mesh.push_fan(centre, &arc_points(curr, half_width, start_angle, sweep));
+        }
+    }
+    return inner_corner;
+}
+
+// Half circle sticking out of the end point in the given direction.
+fn add_round_cap(mesh: &mut Mesh2D, end: Vector2<f32>, outward: Vector2<f32>, half_width: f32) {
+    let outward = outward.normalize();
+    if !outward.x.is_finite() || !outward.y.is_finite() {
+        return;
+    }
+    let start_angle = outward.y.atan2(outward.x) - PI / 2.0;
+    mesh.push_fan(end, &arc_points(end, half_width, start_angle, PI));
+}
+
+fn arc_points(centre: Vector2<f32>, radius: f32, start_angle: f32, sweep: f32) -> Vec<Vector2<f32>> {
+    let step = if radius > DEFAULT_TOLERANCE { 2.0 * (1.0 - DEFAULT_TOLERANCE / radius).acos() } else { PI / 2.0 };
+    let segments = ((sweep.abs() / step).ceil() as usize).clamp(1, 64);
+    return (0..=segments)
+        .map(|i| {
+            let angle = start_angle + sweep * (i as f32 / segments as f32);
+            centre + Vector2::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect();
+}
+
+// Cuts a contour into the "on" parts of the dash pattern.
+fn apply_dashes(contour: &Contour, dashes: &[f32], dash_offset: f32) -> Vec<Vec<Vector2<f32>>> {
+    let pattern_length: f32 = dashes.iter().map(|d| d.max(0.0)).sum();
+    let mut result: Vec<Vec<Vector2<f32>>> = vec![];
+    let mut points = contour.points.clone();
+    if contour.closed && !points.is_empty() {
+        points.push(points[0]);
+    }
+
+    // Find where in the pattern the contour starts.
+    let mut dash_index = 0;
+    let mut remaining = dashes[0].max(0.0);
+    let mut offset = dash_offset.rem_euclid(pattern_length);
+    while offset > 0.0 {
+        if offset < remaining {
+            remaining -= offset;
+            break;
+        }
+        offset -= remaining;
+        dash_index = (dash_index + 1) % dashes.len();
+        remaining = dashes[dash_index].max(0.0);
+    }
+
+    let mut current: Vec<Vector2<f32>> = vec![];
+    for pair in points.windows(2) {
+        let (mut start, end) = (pair[0], pair[1]);
+        let mut length = (end - start).magnitude();
+        let direction = if length > 0.0 { (end - start) / length } else { Vector2::new(0.0, 0.0) };
+        while length > 0.0 {
+            let on = dash_index % 2 == 0;
+            if on && current.is_empty() {
+                current.push(start);
+            }
+            let step = remaining.min(length);
+            start += direction * step;
+            length -= step;
+            remaining -= step;
+            if on {
+                current.push(start);
+            }
+            if remaining <= 0.0 {
+                if on && current.len() > 1 {
+                    result.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                dash_index = (dash_index + 1) % dashes.len();
+                remaining = dashes[dash_index].max(0.0);
+            }
+        }
+    }
+    if current.len() > 1 {
+        result.push(current);
+    }
+    return result;
+}
+
+fn is_ear(points: &[Vector2<f32>], remaining: &[usize], prev: usize, curr: usize, next: usize) -> bool {
+    let (a, b, c) = (points[prev], points[curr], points[next]);
+    // Reflex or flat corners can't be ears.
+    if cross(b - a, c - b) <= 0.0 {
+        return false;
+    }
+    for &other in remaining {
+        if other == prev || other == curr || other == next {
+            continue;
+        }
+        if point_in_triangle(points[other], a, b, c) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn point_in_triangle(p: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>) -> bool {
+    let d1 = cross(b - a, p - a);
+    let d2 = cross(c - b, p - b);
+    let d3 = cross(a - c, p - c);
+    let has_negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    return !(has_negative && has_positive);
+}
+
+fn signed_area(points: &[Vector2<f32>]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += a.x * b.y - b.x * a.y;
+    }
+    return area / 2.0;
+}
+
+fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32) -> Vector2<f32> {
+        return Vector2::new(x, y);
+    }
+
+    fn contour(points: &[(f32, f32)], closed: bool) -> Contour {
+        return Contour {
+            points: points.iter().map(|(x, y)| v(*x, *y)).collect(),
+            closed,
+        }
+    }
+
+    // Sum of the triangle areas, anything covered twice counts twice.
+    fn mesh_area(mesh: &Mesh2D) -> f32 {
+        return mesh.indices.chunks(3)
+            .map(|t| cross(mesh.vertices[t[1] as usize] - mesh.vertices[t[0] as usize], mesh.vertices[t[2] as usize] - mesh.vertices[t[0] as usize]).abs() / 2.0)
+            .sum();
+    }
+
+    // What a flattened round join or cap adds.
+    fn fan_area(radius: f32, sweep: f32) -> f32 {
+        let points = arc_points(v(0.0, 0.0), radius, 0.0, sweep);
+        return points.windows(2).map(|pair| cross(pair[0], pair[1]).abs() / 2.0).sum();
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "expected {} but got {}", expected, actual);
+    }
+
+    #[test]
+    fn concave_polygon_fill() {
+        // An arrow pointing right, concave where the shaft meets the head.
+        let arrow = contour(&[(0.0, 4.0), (6.0, 4.0), (6.0, 0.0), (12.0, 6.0), (6.0, 12.0), (6.0, 8.0), (0.0, 8.0)], true);
+        let mesh = fill(std::slice::from_ref(&arrow));
+        assert_eq!(mesh.indices.len() / 3, arrow.points.len() - 2);
+        assert_close(mesh_area(&mesh), signed_area(&arrow.points).abs(), 1e-3);
+        assert_close(mesh_area(&mesh), 24.0 + 36.0, 1e-3);
+    }
+
+    #[test]
+    fn fill_ignores_winding() {
+        let clockwise = contour(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (5.0, 5.0), (0.0, 10.0)], true);
+        let mut reversed = clockwise.clone();
+        reversed.points.reverse();
+        assert_close(mesh_area(&fill(&[clockwise])), 75.0, 1e-3);
+        assert_close(mesh_area(&fill(&[reversed])), 75.0, 1e-3);
+    }
+
+    #[test]
+    fn flattened_arcs_stay_on_the_circle() {
+        let contours = flatten(&[PathCommand2D::Arc { center: v(5.0, 5.0), radius: 20.0, start_angle: 0.0, end_angle: PI }], DEFAULT_TOLERANCE);
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].points.len() > 3);
+        for point in &contours[0].points {
+            assert_close((point - v(5.0, 5.0)).magnitude(), 20.0, 1e-3);
+        }
+    }
+
+    #[test]
+    fn dashes_split_a_line() {
+        let line = contour(&[(0.0, 0.0), (10.0, 0.0)], false);
+        let dashes = apply_dashes(&line, &[2.0, 3.0], 0.0);
+        let spans: Vec<(f32, f32)> = dashes.iter().map(|d| (d[0].x, d[d.len() - 1].x)).collect();
+        assert_eq!(spans, vec![(0.0, 2.0), (5.0, 7.0)]);
+
+        // Starting one unit into the pattern shortens the first dash and lets the last one run into the end.
+        let dashes = apply_dashes(&line, &[2.0, 3.0], 1.0);
+        let spans: Vec<(f32, f32)> = dashes.iter().map(|d| (d[0].x, d[d.len() - 1].x)).collect();
+        assert_eq!(spans, vec![(0.0, 1.0), (4.0, 6.0), (9.0, 10.0)]);
+    }
+
+    #[test]
+    fn dashes_follow_corners() {
+        let corner = contour(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)], false);
+        let dashes = apply_dashes(&corner, &[6.0, 10.0], 0.0);
+        assert_eq!(dashes.len(), 1);
+        assert_eq!(dashes[0], vec![v(0.0, 0.0), v(4.0, 0.0), v(4.0, 2.0)]);
+    }
+
+    #[test]
+    fn odd_dash_lists_repeat() {
+        let line = contour(&[(0.0, 0.0), (10.0, 0.0)], false);
+        let mut options = StrokeOptions::new(2.0);
+        options.dashes = vec![2.0];
+        let mesh = stroke(&[line], &options);
+        // Dashes at 0, 4 and 8, each a single quad.
+        assert_eq!(mesh.indices.len(), 3 * 6);
+        assert_close(mesh_area(&mesh), 3.0 * 2.0 * 2.0, 1e-3);
+    }
+
+    #[test]
+    fn joins_dont_overlap_the_segments() {
+        let corner = contour(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)], false);
+        let mut options = StrokeOptions::new(2.0);
+        options.join = LineJoin::Bevel;
+        // Both 10x2 segments, less the square they share on the inside, plus the bevel's half square.
+        assert_close(mesh_area(&stroke(std::slice::from_ref(&corner), &options)), 20.0 + 20.0 - 1.0 + 0.5, 1e-3);
+        options.join = LineJoin::Miter;
+        assert_close(mesh_area(&stroke(std::slice::from_ref(&corner), &options)), 20.0 + 20.0 - 1.0 + 1.0, 1e-3);
+        options.join = LineJoin::Round;
+        assert_close(mesh_area(&stroke(&[corner], &options)), 20.0 + 20.0 - 1.0 + fan_area(1.0, PI / 2.0), 1e-3);
+    }
+
+    #[test]
+    fn closed_contours_join_all_around() {
+        let square = contour(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)], true);
+        let mut options = StrokeOptions::new(2.0);
+        options.join = LineJoin::Miter;
+        // A 12x12 square with an 8x8 hole, covered exactly once.
+        assert_close(mesh_area(&stroke(&[square], &options)), 144.0 - 64.0, 1e-3);
+    }
+
+    #[test]
+    fn caps() {
+        let line = contour(&[(0.0, 0.0), (10.0, 0.0)], false);
+        let mut options = StrokeOptions::new(2.0);
+        assert_close(mesh_area(&stroke(std::slice::from_ref(&line), &options)), 20.0, 1e-3);
+        options.cap = LineCap::Square;
+        assert_close(mesh_area(&stroke(std::slice::from_ref(&line), &options)), 24.0, 1e-3);
+        options.cap = LineCap::Round;
+        assert_close(mesh_area(&stroke(&[line], &options)), 20.0 + fan_area(1.0, PI) * 2.0, 1e-3);
+    }
+}
\ No newline at end of file
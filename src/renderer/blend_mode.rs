@@ -0,0 +1,52 @@
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+// How a drawable's colors are combined with what's already been drawn.
+// Shaders output premultiplied alpha, the blend states below all expect that.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum BlendMode {
+    Normal,
+    Additive,
+    Multiply,
+    Screen,
+}
+
+impl BlendMode {
+    pub fn to_blend_state(&self) -> BlendState {
+        // Alpha is always accumulated like normal blending, so coverage keeps working with every mode.
+        let alpha = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        };
+        let color = match self {
+            BlendMode::Normal => alpha,
+            BlendMode::Additive => BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            // src * dst + dst * (1 - src alpha), so transparent parts leave the destination alone
+            BlendMode::Multiply => BlendComponent {
+                src_factor: BlendFactor::Dst,
+                dst_factor: BlendFactor::OneMinusSrcAlpha,
+                operation: BlendOperation::Add,
+            },
+            // src + dst * (1 - src)
+            BlendMode::Screen => BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::OneMinusSrc,
+                operation: BlendOperation::Add,
+            },
+        };
+        return BlendState {
+            color,
+            alpha,
+        };
+    }
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        return BlendMode::Normal;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,154 @@
+use wgpu::{Adapter, Backends, Features, Limits, PowerPreference, PresentMode, TextureFormat, TextureFormatFeatureFlags};
+
+// How frames are paced against the display.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VsyncMode {
+    On, // Waits for the display, never tears
+    Adaptive, // Waits unless a frame is late, then tears instead of waiting a whole refresh. Falls back to On
+    Off, // Doesn't wait, tearing where mailbox presentation isn't available. Falls back to On
+}
+
+// Which kind of surface format is picked when the surface offers both. Colors look the same either way,
+// a linear surface is written through an sRGB view or encoded in an extra pass.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FormatPreference {
+    Srgb, // The hardware encodes to sRGB when writing
+    Linear, // For compositors or capture tools that want a plain unorm surface
+}
+
+// Everything WgpuState is created with. Requests the adapter can't meet are lowered to what it can do.
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    vsync: VsyncMode,
+    backends: Backends,
+    power_preference: PowerPreference,
+    format: FormatPreference,
+    sample_count: u32,
+    features: Features, // Only the ones the adapter supports are enabled
+    limits: Limits,
+}
+
+impl RendererConfig {
+    pub fn new() -> RendererConfig {
+        return RendererConfig {
+            vsync: VsyncMode::On,
+            backends: Backends::all(),
+            power_preference: PowerPreference::default(),
+            format: FormatPreference::Srgb,
+            sample_count: 1,
+            features: Features::TEXTURE_COMPRESSION_BC | Features::TIMESTAMP_QUERY,
+            limits: Limits::downlevel_defaults(),
+        }
+    }
+
+    pub fn with_vsync(mut self, vsync: VsyncMode) -> RendererConfig {
+        self.vsync = vsync;
+        return self;
+    }
+
+    pub fn with_backends(mut self, backends: Backends) -> RendererConfig {
+        self.backends = backends;
+        return self;
+    }
+
+    pub fn with_power_preference(mut self, power_preference: PowerPreference) -> RendererConfig {
+        self.power_preference = power_preference;
+        return self;
+    }
+
+    pub fn with_format(mut self, format: FormatPreference) -> RendererConfig {
+        self.format = format;
+        return self;
+    }
+
+    // 1 turns MSAA off. Pipelines start out with this many samples.
+    pub fn with_msaa(mut self, sample_count: u32) -> RendererConfig {
+        self.sample_count = sample_count.max(1);
+        return self;
+    }
+
+    // Added to the default requests.
+    pub fn with_features(mut self, features: Features) -> RendererConfig {
+        self.features |= features;
+        return self;
+    }
+
+    pub fn without_features(mut self, features: Features) -> RendererConfig {
+        self.features -= features;
+        return self;
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> RendererConfig {
+        self.limits = limits;
+        return self;
+    }
+
+    pub fn get_vsync(&self) -> VsyncMode {
+        return self.vsync;
+    }
+
+    pub fn get_backends(&self) -> Backends {
+        return self.backends;
+    }
+
+    pub fn get_power_preference(&self) -> PowerPreference {
+        return self.power_preference;
+    }
+
+    pub fn get_format(&self) -> FormatPreference {
+        return self.format;
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        return self.sample_count;
+    }
+
+    pub fn get_features(&self) -> Features {
+        return self.features;
+    }
+
+    pub fn get_limits(&self) -> &Limits {
+        return &self.limits;
+    }
+
+    // The requested features the adapter has, the rest are logged and left off.
+    pub fn supported_features(&self, adapter: &Adapter) -> Features {
+        let missing = self.features - adapter.features();
+        if !missing.is_empty() {
+            log::info!("The adapter doesn't support {:?}, continuing without", missing);
+        }
+        return self.features & adapter.features();
+    }
+
+    // The first present mode in the vsync mode's order of preference that the surface supports.
+    pub fn present_mode(&self, supported: &[PresentMode]) -> PresentMode {
+        let preferred: &[PresentMode] = match self.vsync {
+            VsyncMode::On => &[PresentMode::Fifo],
+            VsyncMode::Adaptive => &[PresentMode::FifoRelaxed, PresentMode::Fifo],
+            VsyncMode::Off => &[PresentMode::Mailbox, PresentMode::Immediate, PresentMode::Fifo],
+        };
+        // Fifo has to be supported everywhere.
+        return preferred.iter().copied().find(|mode| supported.contains(mode)).unwrap_or(PresentMode::Fifo);
+    }
+
+    // Picks a usable surface format of the preferred kind, or any usable one when there's none of that kind.
+    pub fn surface_format(&self, supported: &[TextureFormat], usable: impl Fn(TextureFormat) -> bool) -> Option<TextureFormat> {
+        let usable: Vec<TextureFormat> = supported.iter().copied().filter(|format| usable(*format)).collect();
+        let wants_srgb = self.format == FormatPreference::Srgb;
+        return usable.iter().copied().find(|format| format.is_srgb() == wants_srgb).or(usable.first().copied());
+    }
+
+    // The highest sample count up to the requested one that the format can be rendered with.
+    pub fn sample_count_for(adapter: &Adapter, format: TextureFormat, requested: u32) -> u32 {
+        let flags = adapter.get_texture_format_features(format).flags;
+        return [16, 8, 4, 2].into_iter()
+            .find(|count| *count <= requested && flags.sample_count_supported(*count) && flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE))
+            .unwrap_or(1);
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> RendererConfig {
+        return RendererConfig::new();
+    }
+}
\ No newline at end of file
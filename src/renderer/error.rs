@@ -0,0 +1,48 @@
+use std::fmt;
+
+use wgpu::{CreateSurfaceError, RequestDeviceError, TextureFormat};
+
+// Why a WgpuState couldn't be created, launchers can fall back to other settings or tell the user.
+#[derive(Debug)]
+pub enum RendererError {
+    WindowCreationFailed(String),
+    NoAdapter, // No GPU, or none that can present to the window
+    DeviceRequestFailed(RequestDeviceError),
+    SurfaceUnsupported(String), // The window can't be rendered to, or not by the adapter that was found
+    FormatUnsupported(Vec<TextureFormat>), // The formats the surface offered, none of them work with the renderer
+    OutOfMemory, // Acquiring a frame ran out of memory, the renderer can't continue
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            RendererError::WindowCreationFailed(reason) => write!(f, "Failed to create the window: {}", reason),
+            RendererError::NoAdapter => write!(f, "No graphics adapter that can render to the window was found"),
+            RendererError::DeviceRequestFailed(err) => write!(f, "Failed to create the graphics device: {}", err),
+            RendererError::SurfaceUnsupported(reason) => write!(f, "The window can't be rendered to: {}", reason),
+            RendererError::FormatUnsupported(formats) => write!(f, "None of the surface formats are supported: {:?}", formats),
+            RendererError::OutOfMemory => write!(f, "Out of memory while acquiring a frame"),
+        };
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            RendererError::DeviceRequestFailed(err) => Some(err),
+            _ => None,
+        };
+    }
+}
+
+impl From<RequestDeviceError> for RendererError {
+    fn from(err: RequestDeviceError) -> RendererError {
+        return RendererError::DeviceRequestFailed(err);
+    }
+}
+
+impl From<CreateSurfaceError> for RendererError {
+    fn from(err: CreateSurfaceError) -> RendererError {
+        return RendererError::SurfaceUnsupported(err.to_string());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use wgpu::{CommandEncoder, Queue, SurfaceTexture, TextureFormat, TextureView};
+
+// One frame of the window: the surface texture, a view of it and an encoder everything records into.
+// Any number of pipelines can draw into it, it's submitted and presented by finish or when it's dropped.
+pub struct Frame {
+    pub view: TextureView,
+    encoder: Option<CommandEncoder>, // Taken when the frame is submitted
+    texture: Option<SurfaceTexture>,
+    queue: Arc<Queue>,
+}
+
+impl Frame {
+    // The view can have a different format than the texture, like the sRGB version of it.
+    pub fn new(texture: SurfaceTexture, view_format: TextureFormat, encoder: CommandEncoder, queue: Arc<Queue>) -> Frame {
+        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(view_format),
+            ..Default::default()
+        });
+        return Frame {
+            view,
+            encoder: Some(encoder),
+            texture: Some(texture),
+            queue,
+        }
+    }
+
+    pub fn encoder(&mut self) -> &mut CommandEncoder {
+        return self.encoder.as_mut().expect("Frame was already submitted");
+    }
+
+    // The encoder and view at once, which is what drawing into the frame needs.
+    pub fn parts(&mut self) -> (&mut CommandEncoder, &TextureView) {
+        return (self.encoder.as_mut().expect("Frame was already submitted"), &self.view);
+    }
+
+    // Submits everything recorded and presents the texture.
+    pub fn finish(mut self) {
+        self.submit();
+    }
+
+    fn submit(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            self.queue.submit(Some(encoder.finish()));
+        }
+        if let Some(texture) = self.texture.take() {
+            texture.present();
+        }
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        self.submit();
+    }
+}
\ No newline at end of file
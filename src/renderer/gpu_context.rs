@@ -0,0 +1,67 @@
+use std::{cell::RefCell, sync::Arc};
+
+use wgpu::{Adapter, Device, Instance, Queue, Surface};
+use wgpu_glyph::ab_glyph::FontArc;
+
+use super::{ShaderModules, config::RendererConfig, error::RendererError, texture_atlas::TextureAtlas};
+
+// What every window renders with: one device and queue, and the shaders, images and font made for them.
+// Each window has a WgpuState of its own with its surface, they all hold the same context.
+pub struct GpuContext {
+    pub instance: Instance, // Creates the surfaces of windows opened later
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Arc<Queue>, // Shared with frames so they can submit themselves
+    pub renderer_config: RendererConfig, // As it was asked for, every window lowers the sample count for its own format
+    pub shader_modules: RefCell<ShaderModules>, // Only borrowed mutably to hot-reload
+    pub atlas: RefCell<TextureAtlas>, // Images inserted once can be drawn by every window's Pipeline2D
+    pub font: FontArc,
+}
+
+impl GpuContext {
+    // The adapter is picked to present to the first window's surface, windows opened later need one it can present to as well.
+    pub async fn new(instance: Instance, compatible_surface: &Surface, renderer_config: RendererConfig) -> Result<GpuContext, RendererError> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: renderer_config.get_power_preference(),
+                force_fallback_adapter: false,
+                // Request an adapter which can render to our surface
+                compatible_surface: Some(compatible_surface),
+            })
+            .await
+            .ok_or(RendererError::NoAdapter)?;
+
+        let features = renderer_config.supported_features(&adapter);
+        // Create the logical device and command queue
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features,
+                    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+                    limits: renderer_config.get_limits().clone(),
+                        //.using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await?;
+
+        // Load the shaders from disk
+        let shader_modules = ShaderModules::new(&device);
+        let atlas = TextureAtlas::new(&device, &queue);
+        let font = FontArc::try_from_slice(include_bytes!(
+            "../Montserrat-Light.ttf"
+        )).expect("The embedded font has to load");
+
+        return Ok(GpuContext {
+            instance,
+            adapter,
+            device,
+            queue: Arc::new(queue),
+            renderer_config,
+            shader_modules: RefCell::new(shader_modules),
+            atlas: RefCell::new(atlas),
+            font,
+        });
+    }
+}
\ No newline at end of file
@@ -1,11 +1,25 @@
+//Used by HeatMap2D, every texel of r_color is one cell of the grid and gets its color from the ramp
+
 struct VertexOutput {
     @location(0) tex_coord: vec2<f32>,
     @builtin(position) position: vec4<f32>,
 };
 
+struct HeatMapUniform {
+    transform: mat4x4<f32>,
+    //Values at the start and end of the ramp
+    range: vec2<f32>,
+    stop_count: u32,
+    padding: u32,
+    wire_color: vec4<f32>,
+    stop_colors: array<vec4<f32>, 8>,
+    //Four stop positions per vector
+    stop_positions: array<vec4<f32>, 2>,
+};
+
 @group(0)
 @binding(0)
-var<uniform> transform: mat4x4<f32>;
+var<uniform> heat_map: HeatMapUniform;
 
 @vertex
 fn vs_main(
@@ -14,7 +28,7 @@ fn vs_main(
 ) -> VertexOutput {
     var result: VertexOutput;
     result.tex_coord = tex_coord;
-    result.position = transform * position;
+    result.position = heat_map.transform * position;
     return result;
 }
 
@@ -22,14 +36,35 @@ fn vs_main(
 @binding(1)
 var r_color: texture_2d<u32>;
 
+fn stop_position(i: u32) -> f32 {
+    return heat_map.stop_positions[i / 4u][i % 4u];
+}
+
+fn ramp(t: f32) -> vec4<f32> {
+    var color = heat_map.stop_colors[0];
+    for (var i = 1u; i < heat_map.stop_count; i++) {
+        let start = stop_position(i - 1u);
+        let end = stop_position(i);
+        if (t >= start) {
+            color = mix(heat_map.stop_colors[i - 1u], heat_map.stop_colors[i], clamp((t - start) / max(end - start, 0.0001), 0.0, 1.0));
+        }
+    }
+    return color;
+}
+
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    let tex = textureLoad(r_color, vec2<i32>(vertex.tex_coord * 256.0), 0);
-    let v = f32(tex.x) / 255.0;
-    return vec4<f32>(1.0 - (v * 5.0), 1.0 - (v * 15.0), 1.0 - (v * 50.0), 1.0);
+    let size = vec2<i32>(textureDimensions(r_color));
+    let cell = clamp(vec2<i32>(vertex.tex_coord * vec2<f32>(size)), vec2(0), size - 1);
+    let value = f32(textureLoad(r_color, cell, 0).x);
+    let t = clamp((value - heat_map.range.x) / max(heat_map.range.y - heat_map.range.x, 0.0001), 0.0, 1.0);
+    let color = ramp(t);
+    //Blending expects premultiplied alpha
+    return vec4<f32>(color.rgb * color.a, color.a);
 }
 
 @fragment
 fn fs_wire(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.0, 0.5, 0.0, 0.5);
+    let color = heat_map.wire_color;
+    return vec4<f32>(color.rgb * color.a, color.a);
 }
\ No newline at end of file
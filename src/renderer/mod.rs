@@ -1,89 +1,213 @@
-use std::{borrow::Cow, rc::Rc};
+pub mod texture_atlas;
+pub mod blend_mode;
+pub mod shader_reload;
+pub mod preprocessor;
+pub mod error;
+pub mod config;
+pub mod frame;
+pub mod gpu_context;
+
+use std::{borrow::Cow, rc::Rc, collections::HashMap, path::Path, sync::Arc};
+use shader_reload::{ShaderWatcher, validate_wgsl};
+use preprocessor::preprocess;
+use error::RendererError;
+use config::RendererConfig;
+use frame::Frame;
+use gpu_context::GpuContext;
 use cgmath::Vector2;
-use wgpu::{Device, ShaderModule, ColorTargetState, SurfaceConfiguration, Queue, Surface, TextureFormat, Features};
-use winit::{
-    window::{Window, WindowBuilder}, event_loop::EventLoop, dpi::PhysicalSize}
-;
+use wgpu::{Device, DownlevelFlags, ShaderModule, ColorTargetState, SurfaceConfiguration, Surface, SurfaceError, SurfaceTexture, TextureFormat};
+use winit::{window::Window, dpi::PhysicalSize};
 
 pub const DEFAULT_SCREEN_WIDTH: u32 = 1366;
 pub const DEFAULT_SCREEN_HEIGHT: u32 = 768;
 
+// One window and its surface. The device and everything else windows share is in the GpuContext.
 pub struct WgpuState {
-    pub window: Window,
-    pub surface: Surface,
+    pub surface: Surface, // Declared before the window so it's dropped first
+    pub window: Arc<Window>,
+    pub gpu: Rc<GpuContext>,
     pub swapchain_format: TextureFormat,
-    pub device: Device,
+    pub render_format: TextureFormat, // What everything is drawn in, it turns the linear colors the shaders output into what's displayed
+    pub encode_srgb: bool, // The surface can't be written to in render_format, the frame is drawn elsewhere and encoded into it by hand
+    pub renderer_config: RendererConfig, // What was asked for, with the sample count lowered to what the adapter supports for this surface
     pub config: SurfaceConfiguration,
-    pub queue: Queue,
 
-    pub shader_modules: ShaderModules,
     pub color_target: ColorTargetState,
+    surface_configured: bool, // False while the window has no area, there's nothing to draw to then
+    reconfigure: bool, // The last frame was suboptimal, the surface is configured again before the next one
 }
 
 impl WgpuState {
-    pub async unsafe fn new(window: Window) -> WgpuState {
+    pub async fn new(window: Arc<Window>) -> Result<WgpuState, RendererError> {
+        return Self::with_config(window, RendererConfig::new()).await;
+    }
+
+    // Creates the GpuContext along with the first window's state.
+    pub async fn with_config(window: Arc<Window>, renderer_config: RendererConfig) -> Result<WgpuState, RendererError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: renderer_config.get_backends(),
+            ..Default::default()
+        });
+
+        // SAFETY: the surface must not outlive the window. WgpuState keeps its own handle to the window and drops the surface first.
+        let surface = unsafe { instance.create_surface(window.as_ref())? };
+        let gpu = GpuContext::new(instance, &surface, renderer_config).await?;
+        return Self::from_surface(Rc::new(gpu), window, surface);
+    }
+
+    // Another window rendered with the same device, so it can share textures, shaders and fonts with the others.
+    pub fn with_context(gpu: Rc<GpuContext>, window: Arc<Window>) -> Result<WgpuState, RendererError> {
+        // SAFETY: same as in with_config, the window handle is kept until the surface is gone.
+        let surface = unsafe { gpu.instance.create_surface(window.as_ref())? };
+        if !gpu.adapter.is_surface_supported(&surface) {
+            return Err(RendererError::SurfaceUnsupported("the shared adapter can't present to it".to_string()));
+        }
+        return Self::from_surface(gpu, window, surface);
+    }
+
+    fn from_surface(gpu: Rc<GpuContext>, window: Arc<Window>, surface: Surface) -> Result<WgpuState, RendererError> {
         let size = window.inner_size();
-        let instance = wgpu::Instance::default();
-
-        let surface = instance.create_surface(&window).unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                // Request an adapter which can render to our surface
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .expect("Failed to find an appropriate adapter");
-    
-        let mut features = wgpu::Features::default();
-        features.set(Features::TEXTURE_COMPRESSION_BC, true);
-        // Create the logical device and command queue
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features,
-                    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                    limits: wgpu::Limits::downlevel_defaults(),
-                        //.using_resolution(adapter.limits()),
-                },
-                None,
-            )
-            .await
-            .expect("Failed to create device");
-    
-        let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-        //let swapchain_format = TextureFormat::
-        let color_target: ColorTargetState = swapchain_format.into();
+        let swapchain_capabilities = surface.get_capabilities(&gpu.adapter);
+        if swapchain_capabilities.formats.is_empty() {
+            return Err(RendererError::SurfaceUnsupported("the adapter can't present to it".to_string()));
+        }
+        let renderer_config = gpu.renderer_config.clone();
+        let swapchain_format = renderer_config.surface_format(&swapchain_capabilities.formats, Self::is_supported_format)
+            .ok_or(RendererError::FormatUnsupported(swapchain_capabilities.formats.clone()))?;
+
+        // Shaders work with linear colors, sRGB formats encode them on write and float ones are linear already.
+        // A plain unorm surface is written through an sRGB view of it where the backend allows that.
+        let srgb_view = swapchain_format.add_srgb_suffix();
+        let view_formats = if srgb_view != swapchain_format && gpu.adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::SURFACE_VIEW_FORMATS) {
+            vec![srgb_view]
+        } else {
+            vec![]
+        };
+        let linear_surface = swapchain_format.is_srgb() || swapchain_format == TextureFormat::Rgba16Float || !view_formats.is_empty();
+        let render_format = if linear_surface { view_formats.first().copied().unwrap_or(swapchain_format) } else { TextureFormat::Rgba8UnormSrgb };
+        let encode_srgb = !linear_surface;
+        if encode_srgb {
+            log::info!("{:?} has no sRGB view, frames are encoded in an extra pass", swapchain_format);
+        }
+
+        let sample_count = RendererConfig::sample_count_for(&gpu.adapter, render_format, renderer_config.get_sample_count());
+        if sample_count != renderer_config.get_sample_count() {
+            log::warn!("{} MSAA samples aren't supported with {:?}, using {}", renderer_config.get_sample_count(), render_format, sample_count);
+        }
+        let renderer_config = renderer_config.with_msaa(sample_count);
+        let color_target: ColorTargetState = render_format.into();
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: renderer_config.present_mode(&swapchain_capabilities.present_modes),
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
             //alpha_mode: swapchain_capabilities.alpha_modes[0],
-            view_formats: vec![],
+            view_formats,
         };
 
-        // Load the shaders from disk
-        let shader_modules = ShaderModules::new(&device);
-         
-        surface.configure(&device, &config);
+        // A window that starts out minimised gets its surface once it has a size.
+        let surface_configured = size.width > 0 && size.height > 0;
+        if surface_configured {
+            surface.configure(&gpu.device, &config);
+        }
 
         let r_state = WgpuState {
-            window,
             surface,
+            window,
+            gpu,
             swapchain_format,
-            device,
+            render_format,
+            encode_srgb,
+            renderer_config,
             config,
-            queue,
-            shader_modules,
             color_target,
+            surface_configured,
+            reconfigure: false,
         };
-        return r_state;
+        return Ok(r_state);
+    }
+
+    // Applies a new window size to the surface. Returns false for a zero-sized (minimised) window,
+    // which leaves the surface alone until it has a size again.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> bool {
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.surface_configured = size.width > 0 && size.height > 0;
+        if self.surface_configured {
+            self.surface.configure(&self.gpu.device, &self.config);
+        }
+        self.reconfigure = false;
+        return self.surface_configured;
+    }
+
+    pub fn is_surface_configured(&self) -> bool {
+        return self.surface_configured;
+    }
+
+    // The next texture to draw to. Ok(None) means this frame should be skipped: the window is minimised,
+    // acquiring timed out, or the surface was lost or outdated and configuring it again didn't help right away.
+    pub fn acquire_frame(&mut self) -> Result<Option<SurfaceTexture>, RendererError> {
+        if self.reconfigure {
+            self.resize(self.window.inner_size());
+        }
+        if !self.surface_configured {
+            return Ok(None);
+        }
+        let frame = match self.surface.get_current_texture() {
+            Err(SurfaceError::Lost) | Err(SurfaceError::Outdated) => {
+                // The window changed or the display went away, the surface has to be configured again before it works.
+                if !self.resize(self.window.inner_size()) {
+                    return Ok(None);
+                }
+                self.surface.get_current_texture()
+            }
+            result => result,
+        };
+        return match frame {
+            Ok(frame) => {
+                self.reconfigure = frame.suboptimal;
+                Ok(Some(frame))
+            }
+            Err(SurfaceError::OutOfMemory) => Err(RendererError::OutOfMemory),
+            Err(err) => {
+                log::warn!("Skipping a frame: {}", err);
+                Ok(None)
+            }
+        };
+    }
+
+    // Acquires the next frame and an encoder to record it with, Ok(None) when the frame should be skipped like acquire_frame.
+    pub fn begin_frame(&mut self) -> Result<Option<Frame>, RendererError> {
+        let texture = match self.acquire_frame()? {
+            Some(texture) => texture,
+            None => return Ok(None),
+        };
+        let encoder = self.gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Frame Encoder"),
+        });
+        let view_format = if self.encode_srgb { self.swapchain_format } else { self.render_format };
+        return Ok(Some(Frame::new(texture, view_format, encoder, self.gpu.queue.clone())));
+    }
+
+    // Submits and presents the frame, dropping it does the same.
+    pub fn end_frame(&self, frame: Frame) {
+        frame.finish();
+    }
+
+    // The highest sample count up to the requested one that can be used to render to the surface.
+    pub fn supported_sample_count(&self, requested: u32) -> u32 {
+        return RendererConfig::sample_count_for(&self.gpu.adapter, self.render_format, requested);
+    }
+
+    // Formats the 2D pipelines can render and blend into, the post-processing targets use the same format.
+    fn is_supported_format(format: TextureFormat) -> bool {
+        return matches!(format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb |
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb |
+            TextureFormat::Rgba16Float | TextureFormat::Rgb10a2Unorm
+        );
     }
 
     pub fn get_width(&mut self) -> f32 {
@@ -98,24 +222,150 @@ impl WgpuState {
         let size = self.window.inner_size();
         return Vector2::new(size.width as f32, size.height as f32);
     }
+
+    // Physical pixels per logical pixel, drawables are laid out in logical pixels.
+    pub fn get_scale_factor(&self) -> f32 {
+        return self.window.scale_factor() as f32;
+    }
+
+    // The window size in logical pixels, the size the root drawable gets.
+    pub fn get_logical_size(&self) -> Vector2<f32> {
+        let size = self.window.inner_size();
+        let scale_factor = self.get_scale_factor();
+        return Vector2::new(size.width as f32 / scale_factor, size.height as f32 / scale_factor);
+    }
+
+    // Turns a window position from an event, which winit gives in physical pixels, into logical pixels.
+    pub fn to_logical(&self, x: f64, y: f64) -> Vector2<f32> {
+        let scale_factor = self.get_scale_factor();
+        return Vector2::new(x as f32 / scale_factor, y as f32 / scale_factor);
+    }
 }
 
+// Every shader file the crate ships with, hot-reload looks for files with the same names.
+const EMBEDDED_SHADERS: [(&str, &str); 5] = [
+    ("ui.wgsl", include_str!("ui.wgsl")),
+    ("sdf.wgsl", include_str!("sdf.wgsl")),
+    ("post_common.wgsl", include_str!("post_common.wgsl")),
+    ("post.wgsl", include_str!("post.wgsl")),
+    ("mesh.wgsl", include_str!("mesh.wgsl")),
+];
+
+// The file each module is built from, anything else it needs comes in through #include.
+const MODULES: [(&str, &str); 3] = [
+    ("ui.wgsl", "UI Shader"),
+    ("post.wgsl", "Post Shader"),
+    ("mesh.wgsl", "Mesh Shader"),
+];
+
 pub struct ShaderModules {
-    pub ui_shader: ShaderModule
+    pub ui_shader: ShaderModule,
+    pub post_shader: ShaderModule,
+    pub mesh_shader: ShaderModule,
+    sources: HashMap<String, String>, // What the modules were built from, embedded or from disk
+    watcher: Option<ShaderWatcher>,
+    generation: u64, // Bumped whenever a module is swapped, pipelines and variants built before that are stale
 }
 
 impl ShaderModules {
     pub fn new(device: &Device) -> ShaderModules {
-        let create_shader = |wgsl_source: &str, label: &str| {
-            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(label),
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(wgsl_source)),
-            });
-            return shader;
+        let sources: HashMap<String, String> = EMBEDDED_SHADERS.iter().map(|(name, source)| (name.to_string(), source.to_string())).collect();
+        let build = |name: &str, label: &str| {
+            let wgsl = preprocess(&sources[name], name, &sources, &[]).expect("Embedded shaders have to preprocess");
+            return Self::create_shader(device, &wgsl, label);
         };
-
         return ShaderModules {
-            ui_shader: create_shader(include_str!("ui.wgsl"), "UI Shader"),
+            ui_shader: build("ui.wgsl", "UI Shader"),
+            post_shader: build("post.wgsl", "Post Shader"),
+            mesh_shader: build("mesh.wgsl", "Mesh Shader"),
+            sources,
+            watcher: None,
+            generation: 0,
+        }
+    }
+
+    fn create_shader(device: &Device, wgsl_source: &str, label: &str) -> ShaderModule {
+        return device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(wgsl_source)),
+        });
+    }
+
+    // Runs the preprocessor over a source, #include can pull in any of the shader files.
+    pub fn preprocess(&self, source: &str, label: &str, defines: &[(&str, &str)]) -> Result<String, String> {
+        return preprocess(source, label, &self.sources, defines);
+    }
+
+    // Builds a specialised module, like one of the shader files with extra defines or user code on top of them.
+    // The result is validated first so a broken variant is an error instead of a device panic.
+    pub fn create_variant(&self, device: &Device, source: &str, label: &str, defines: &[(&str, &str)]) -> Result<ShaderModule, String> {
+        let wgsl = self.preprocess(source, label, defines)?;
+        validate_wgsl(&wgsl, label)?;
+        return Ok(Self::create_shader(device, &wgsl, label));
+    }
+
+    // The current source of one of the shader files.
+    pub fn get_source(&self, name: &str) -> &str {
+        return &self.sources[name];
+    }
+
+    pub fn get_generation(&self) -> u64 {
+        return self.generation;
+    }
+
+    // Loads shaders from the directory instead and reloads them when they change, only in debug builds.
+    // Files that aren't in the directory keep using the embedded version.
+    pub fn enable_hot_reload(&mut self, device: &Device, dir: impl AsRef<Path>) {
+        if !cfg!(debug_assertions) {
+            log::warn!("Shader hot-reload is only available in debug builds");
+            return;
+        }
+        self.watcher = Some(ShaderWatcher::new(dir));
+        self.poll_hot_reload(device);
+    }
+
+    // Swaps in the files that changed on disk. If any module fails to build from them, everything keeps its old version.
+    pub fn poll_hot_reload(&mut self, device: &Device) -> bool {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return false,
+        };
+        if !watcher.poll() {
+            return false;
+        }
+        let mut candidates = self.sources.clone();
+        for (name, embedded) in EMBEDDED_SHADERS {
+            candidates.insert(name.to_string(), watcher.read(name).unwrap_or(embedded.to_string()));
+        }
+        if candidates == self.sources {
+            return false;
+        }
+
+        let mut modules: Vec<(&str, ShaderModule)> = vec![];
+        for (name, label) in MODULES {
+            let result = preprocess(&candidates[name], name, &candidates, &[]).and_then(|wgsl| {
+                validate_wgsl(&wgsl, label)?;
+                return Ok(wgsl);
+            });
+            match result {
+                Ok(wgsl) => modules.push((label, Self::create_shader(device, &wgsl, label))),
+                Err(err) => {
+                    log::error!("{} failed to compile, keeping the old shaders:\n{}", label, err);
+                    return false;
+                }
+            }
+        }
+        for (label, module) in modules {
+            match label {
+                "UI Shader" => self.ui_shader = module,
+                "Post Shader" => self.post_shader = module,
+                _ => self.mesh_shader = module,
+            }
         }
+        // Variants are built from the sources as well, even a file no module includes can change one.
+        self.sources = candidates;
+        self.generation += 1;
+        log::info!("Reloaded shaders");
+        return true;
     }
 }
\ No newline at end of file
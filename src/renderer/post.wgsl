@@ -0,0 +1,61 @@
+#include "post_common.wgsl"
+
+
+@fragment
+fn copy(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source_texture, source_sampler, vertex.uv);
+}
+
+//One direction of a separable gaussian blur, params[0].x is the radius in pixels
+//Big radii spread at most 32 taps on each side further apart
+@fragment
+fn blur(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    let radius = post.params[0].x;
+    let sigma = max(radius / 3.0, 0.0001);
+    let spacing = max(radius / 32.0, 1.0);
+    let taps = i32(ceil(radius / spacing));
+    var sum = vec4(0.0);
+    var total = 0.0;
+    for (var i = -taps; i <= taps; i++) {
+        let offset = f32(i) * spacing;
+        let weight = exp(-(offset * offset) / (2.0 * sigma * sigma));
+        let uv = vertex.uv + post.direction * post.texel * offset;
+        sum += textureSampleLevel(source_texture, source_sampler, uv, 0.0) * weight;
+        total += weight;
+    }
+    return sum / total;
+}
+
+//params[0] is brightness, contrast, saturation and params[1] the tint
+@fragment
+fn color_grade(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    let source = textureSample(source_texture, source_sampler, vertex.uv);
+    //Grading works on straight colors, the frame is premultiplied
+    var color = source.rgb / max(source.a, 0.0001);
+    color *= post.params[1].rgb;
+    color += post.params[0].x;
+    color = (color - 0.5) * post.params[0].y + 0.5;
+    let luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    color = clamp(mix(vec3(luma), color, post.params[0].z), vec3(0.0), vec3(1.0));
+    return vec4(color * source.a, source.a);
+}
+
+//params[0] is intensity, radius and softness, params[1] the color the edges fade to
+@fragment
+fn vignette(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    let source = textureSample(source_texture, source_sampler, vertex.uv);
+    //0 in the centre and 1 in the corners
+    let distance = length(vertex.uv - 0.5) * 1.4142;
+    let shade = smoothstep(post.params[0].y - post.params[0].z, post.params[0].y, distance) * post.params[0].x;
+    let color = vec4(post.params[1].rgb * source.a, source.a);
+    return mix(source, color, clamp(shade * post.params[1].a, 0.0, 1.0));
+}
+
+//The last pass when the frame has no sRGB format to write through, the scene is linear and the display expects sRGB
+@fragment
+fn encode_srgb(vertex: PostVertexOutput) -> @location(0) vec4<f32> {
+    let source = textureSample(source_texture, source_sampler, vertex.uv);
+    let color = clamp(source.rgb / max(source.a, 0.0001), vec3(0.0), vec3(1.0));
+    let encoded = select(1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, color * 12.92, color <= vec3(0.0031308));
+    return vec4(encoded * source.a, source.a);
+}
\ No newline at end of file
@@ -0,0 +1,36 @@
+//Shared by every fullscreen pass Pipeline2D runs on the finished frame, included by post.wgsl and user effects
+
+struct PostVertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) position: vec4<f32>,
+};
+
+struct PostUniform {
+    params: array<vec4<f32>, 2>,
+    //Size of one source pixel in uv
+    texel: vec2<f32>,
+    //Which way a blur pass goes, (1, 0) or (0, 1)
+    direction: vec2<f32>,
+};
+
+@group(0)
+@binding(0)
+var source_texture: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var source_sampler: sampler;
+
+@group(1)
+@binding(0)
+var<uniform> post: PostUniform;
+
+//A single triangle that covers the whole target
+@vertex
+fn vertex(@builtin(vertex_index) index: u32) -> PostVertexOutput {
+    var out: PostVertexOutput;
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+    out.uv = uv;
+    out.position = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
\ No newline at end of file
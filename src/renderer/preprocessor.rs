@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+
+// Deep include chains are almost certainly a file including itself through another one.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Runs the directives WGSL doesn't have over a shader before it's compiled:
+//   #include "file.wgsl"   pastes another shader file in, every file is only included once and can't include itself
+//   #define NAME value     replaces NAME with the value from then on, the value can be left out to only use it with #ifdef
+//   #ifdef NAME / #ifndef NAME / #else / #endif
+// Defines passed in from code act like #define lines at the top of the source.
+pub fn preprocess(source: &str, label: &str, files: &HashMap<String, String>, defines: &[(&str, &str)]) -> Result<String, String> {
+    let mut preprocessor = Preprocessor {
+        files,
+        defines: defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        included: HashSet::new(),
+        stack: vec![],
+        output: String::new(),
+    };
+    preprocessor.process(source, label, 0)?;
+    return Ok(preprocessor.output);
+}
+
+struct Preprocessor<'a> {
+    files: &'a HashMap<String, String>,
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    stack: Vec<String>, // The files being processed, the outermost first
+    output: String,
+}
+
+// One #ifdef that hasn't been closed yet.
+struct Condition {
+    active: bool, // Whether lines in the current branch are kept
+    parent_active: bool,
+    has_else: bool,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn process(&mut self, source: &str, label: &str, depth: usize) -> Result<(), String> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!("{}: includes are nested more than {} deep", label, MAX_INCLUDE_DEPTH));
+        }
+        self.stack.push(label.to_string());
+        let mut conditions: Vec<Condition> = vec![];
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: String| format!("{}:{}: {}", label, number + 1, message);
+            let active = conditions.last().map(|condition| condition.active).unwrap_or(true);
+            let trimmed = line.trim();
+            if !trimmed.starts_with('#') {
+                if active {
+                    let line = self.substitute(line);
+                    self.output.push_str(&line);
+                    self.output.push('\n');
+                }
+                continue;
+            }
+
+            let (directive, argument) = match trimmed.split_once(char::is_whitespace) {
+                Some((directive, argument)) => (directive, argument.trim()),
+                None => (trimmed, ""),
+            };
+            match directive {
+                "#ifdef" | "#ifndef" => {
+                    let defined = self.defines.contains_key(argument);
+                    conditions.push(Condition {
+                        active: active && (defined == (directive == "#ifdef")),
+                        parent_active: active,
+                        has_else: false,
+                    });
+                }
+                "#else" => {
+                    let condition = conditions.last_mut().ok_or_else(|| error("#else without #ifdef".to_string()))?;
+                    if condition.has_else {
+                        return Err(error("second #else for the same #ifdef".to_string()));
+                    }
+                    condition.has_else = true;
+                    condition.active = condition.parent_active && !condition.active;
+                }
+                "#endif" => {
+                    conditions.pop().ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => {}
+                "#define" => {
+                    let (name, value) = match argument.split_once(char::is_whitespace) {
+                        Some((name, value)) => (name, value.trim()),
+                        None => (argument, ""),
+                    };
+                    if name.is_empty() {
+                        return Err(error("#define needs a name".to_string()));
+                    }
+                    self.defines.insert(name.to_string(), value.to_string());
+                }
+                "#include" => {
+                    let name = argument.trim_matches('"');
+                    if self.stack.iter().any(|file| file == name) {
+                        return Err(error(format!("{} includes itself through {}", name, self.stack.join(" -> "))));
+                    }
+                    if self.included.contains(name) {
+                        continue;
+                    }
+                    let included = self.files.get(name).ok_or_else(|| error(format!("can't include {}, there's no such shader", name)))?;
+                    self.included.insert(name.to_string());
+                    self.process(included, name, depth + 1)?;
+                }
+                _ => return Err(error(format!("unknown directive {}", directive))),
+            }
+        }
+        if !conditions.is_empty() {
+            return Err(format!("{}: {} #ifdef without #endif", label, conditions.len()));
+        }
+        self.stack.pop();
+        return Ok(());
+    }
+
+    // Swaps defined names for their values, only whole identifiers are replaced.
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.is_empty() {
+            return line.to_string();
+        }
+        let mut result = String::with_capacity(line.len());
+        let mut identifier = String::new();
+        for c in line.chars().chain(std::iter::once('\n')) {
+            if c.is_alphanumeric() || c == '_' {
+                identifier.push(c);
+                continue;
+            }
+            if !identifier.is_empty() {
+                match self.defines.get(&identifier) {
+                    Some(value) if !value.is_empty() => result.push_str(value),
+                    _ => result.push_str(&identifier),
+                }
+                identifier.clear();
+            }
+            if c != '\n' {
+                result.push(c);
+            }
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader_files(list: &[(&str, &str)]) -> HashMap<String, String> {
+        return list.iter().map(|(name, source)| (name.to_string(), source.to_string())).collect();
+    }
+
+    fn lines(output: &str) -> Vec<&str> {
+        return output.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+    }
+
+    #[test]
+    fn defines_replace_whole_identifiers() {
+        let source = "#define SIZE 4\nlet a = SIZE;\nlet b = SIZE_2 + MY_SIZE;\nlet c = SIZE*SIZE;";
+        let output = preprocess(source, "test", &HashMap::new(), &[]).unwrap();
+        assert_eq!(lines(&output), vec!["let a = 4;", "let b = SIZE_2 + MY_SIZE;", "let c = 4*4;"]);
+    }
+
+    #[test]
+    fn code_defines_act_like_define_lines() {
+        let source = "#ifdef FAST\nfast();\n#endif\nlet n = COUNT;";
+        let output = preprocess(source, "test", &HashMap::new(), &[("FAST", ""), ("COUNT", "3")]).unwrap();
+        assert_eq!(lines(&output), vec!["fast();", "let n = 3;"]);
+    }
+
+    #[test]
+    fn nested_ifdef_and_else() {
+        let source = "\
+#ifdef A
+a();
+#ifdef B
+ab();
+#else
+a_not_b();
+#endif
+#else
+not_a();
+#ifndef B
+not_a_not_b();
+#else
+not_a_b();
+#endif
+#endif";
+        let run = |defines: &[(&str, &str)]| preprocess(source, "test", &HashMap::new(), defines).unwrap();
+        assert_eq!(lines(&run(&[("A", ""), ("B", "")])), vec!["a();", "ab();"]);
+        assert_eq!(lines(&run(&[("A", "")])), vec!["a();", "a_not_b();"]);
+        assert_eq!(lines(&run(&[("B", "")])), vec!["not_a();", "not_a_b();"]);
+        assert_eq!(lines(&run(&[])), vec!["not_a();", "not_a_not_b();"]);
+    }
+
+    #[test]
+    fn skipped_branches_ignore_their_directives() {
+        let source = "#ifdef MISSING\n#include \"missing.wgsl\"\n#define X 1\n#whatever\n#endif\nlet x = X;";
+        let output = preprocess(source, "test", &HashMap::new(), &[]).unwrap();
+        assert_eq!(lines(&output), vec!["let x = X;"]);
+    }
+
+    #[test]
+    fn includes_are_pasted_once() {
+        let files = shader_files(&[("common.wgsl", "common();"), ("a.wgsl", "#include \"common.wgsl\"\na();"), ("b.wgsl", "#include \"common.wgsl\"\nb();")]);
+        let source = "#include \"a.wgsl\"\n#include \"b.wgsl\"\nmain();";
+        let output = preprocess(source, "main.wgsl", &files, &[]).unwrap();
+        assert_eq!(lines(&output), vec!["common();", "a();", "b();", "main();"]);
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let files = shader_files(&[("a.wgsl", "#include \"b.wgsl\""), ("b.wgsl", "#include \"a.wgsl\"")]);
+        let err = preprocess("#include \"a.wgsl\"", "main.wgsl", &files, &[]).unwrap_err();
+        assert!(err.contains("a.wgsl includes itself"), "{}", err);
+
+        let files = shader_files(&[("main.wgsl", "")]);
+        let err = preprocess("#include \"main.wgsl\"", "main.wgsl", &files, &[]).unwrap_err();
+        assert!(err.contains("main.wgsl includes itself"), "{}", err);
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        let files = shader_files(&[("bad.wgsl", "ok();\n#endif")]);
+        assert_eq!(preprocess("x();\n#include \"bad.wgsl\"", "main", &files, &[]).unwrap_err(), "bad.wgsl:2: #endif without #ifdef");
+        assert_eq!(preprocess("#include \"nope.wgsl\"", "main", &files, &[]).unwrap_err(), "main:1: can't include nope.wgsl, there's no such shader");
+        assert_eq!(preprocess("#pragma once", "main", &files, &[]).unwrap_err(), "main:1: unknown directive #pragma");
+        assert_eq!(preprocess("#ifdef A\n#else\n#else\n#endif", "main", &files, &[]).unwrap_err(), "main:3: second #else for the same #ifdef");
+        assert_eq!(preprocess("#ifdef A\n#ifdef B\n#endif", "main", &files, &[]).unwrap_err(), "main: 1 #ifdef without #endif");
+        assert_eq!(preprocess("#define", "main", &files, &[]).unwrap_err(), "main:1: #define needs a name");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+//Signed distance functions shared between shaders, negative inside the shape and in pixels when the inputs are
+
+//p is relative to the centre of the rectangle, the radius is clamped so the corners can't overlap
+fn rounded_rect_distance(p: vec2<f32>, half_size: vec2<f32>, radius: f32) -> f32 {
+    let r = clamp(radius, 0.0, min(half_size.x, half_size.y));
+    let q = abs(p) - half_size + r;
+    return length(max(q, vec2(0.0))) + min(max(q.x, q.y), 0.0) - r;
+}
+
+fn circle_distance(p: vec2<f32>, radius: f32) -> f32 {
+    return length(p) - radius;
+}
\ No newline at end of file
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+// How often the shader directory is checked, reading every file's metadata each frame is wasteful.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Keeps an eye on the .wgsl files in a directory by polling their modification times.
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: impl AsRef<Path>) -> ShaderWatcher {
+        return ShaderWatcher {
+            dir: dir.as_ref().to_path_buf(),
+            modified: HashMap::new(),
+            last_poll: None,
+        }
+    }
+
+    // True when a shader was added, changed or removed since the last call. The first call always is.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.map(|last| last.elapsed() < POLL_INTERVAL).unwrap_or(false) {
+            return false;
+        }
+        self.last_poll = Some(Instant::now());
+
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::warn!("Can't watch shader directory {}: {}", self.dir.display(), err);
+                return false;
+            }
+        };
+        let mut modified: HashMap<PathBuf, SystemTime> = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|extension| extension == "wgsl").unwrap_or(false) {
+                if let Ok(time) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                    modified.insert(path, time);
+                }
+            }
+        }
+        let changed = modified != self.modified;
+        self.modified = modified;
+        return changed;
+    }
+
+    pub fn read(&self, name: &str) -> Option<String> {
+        return fs::read_to_string(self.dir.join(name)).ok();
+    }
+}
+
+// Parses and validates WGSL with naga, the error is formatted with the offending source lines.
+pub fn validate_wgsl(source: &str, label: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| err.emit_to_string_with_path(source, label))?;
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|err| err.emit_to_string_with_path(source, label))?;
+    return Ok(());
+}
\ No newline at end of file
@@ -0,0 +1,506 @@
+use std::{collections::HashMap, sync::atomic::AtomicU64};
+
+use wgpu::{BindGroup, BindGroupLayout, Device, Queue, Sampler, Texture, TextureView};
+
+pub const ATLAS_PAGE_SIZE: u32 = 2048;
+// Border kept around every image and filled with its edge texels, so linear filtering doesn't bleed neighbours in.
+const ATLAS_PADDING: u32 = 1;
+// Every page keeps a small white block in its top left corner so untextured quads can join any batch.
+const WHITE_BLOCK_SIZE: u32 = 4;
+
+static ATLAS_IMAGE_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AtlasImageId(u64);
+
+impl AtlasImageId {
+    pub fn dummy() -> AtlasImageId {
+        return AtlasImageId(0);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Where an image currently lives, uvs are normalized to the page size.
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+struct AtlasEntry {
+    page: usize,
+    shelf: usize,
+    rect: AtlasRect,
+    pixels: Vec<u8>, // Kept around so the atlas can be re-packed
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    cursor_x: u32,
+    live: u32,
+}
+
+// Hands out the rects of one page shelf by shelf, the pixels are written by the atlas.
+struct ShelfPacker {
+    shelves: Vec<Shelf>,
+    next_shelf_y: u32,
+}
+
+struct AtlasPage {
+    texture: Texture,
+    bind_group: BindGroup,
+    packer: ShelfPacker,
+}
+
+// Packs many small RGBA images into a few large textures using shelf packing.
+pub struct TextureAtlas {
+    pub bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    pages: Vec<AtlasPage>,
+    entries: HashMap<AtlasImageId, AtlasEntry>,
+}
+
+impl TextureAtlas {
+    pub fn new(device: &Device, queue: &Queue) -> TextureAtlas {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Atlas Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture Atlas Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut atlas = TextureAtlas {
+            bind_group_layout,
+            sampler,
+            pages: vec![],
+            entries: HashMap::new(),
+        };
+        // Always have one page so untextured quads have something to bind.
+        atlas.add_page(device, queue);
+        return atlas;
+    }
+
+    // Copies the RGBA8 pixels into the atlas, returns None if the image can never fit on a page.
+    pub fn insert(&mut self, device: &Device, queue: &Queue, width: u32, height: u32, pixels: &[u8]) -> Option<AtlasImageId> {
+        if !Self::fits_on_page(width, height) {
+            log::warn!("A {}x{} image is too large for a {} texture atlas page", width, height, ATLAS_PAGE_SIZE);
+            return None;
+        }
+        if (width as usize).checked_mul(height as usize).and_then(|texels| texels.checked_mul(4)) != Some(pixels.len()) {
+            log::warn!("TextureAtlas::insert was given {} bytes for a {}x{} image", pixels.len(), width, height);
+            return None;
+        }
+        let (page, shelf, rect) = self.allocate(device, queue, width, height)?;
+        let id = AtlasImageId(ATLAS_IMAGE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst));
+        let entry = AtlasEntry {
+            page,
+            shelf,
+            rect,
+            pixels: pixels.to_vec(),
+        };
+        self.upload(queue, &entry);
+        self.entries.insert(id, entry);
+        return Some(id);
+    }
+
+    // The space is only reused once every image on its shelf is removed, repack to get back the gaps in shelves that are still in use.
+    // An emptied shelf is cleared so whatever goes there next doesn't sit next to stale texels.
+    pub fn remove(&mut self, queue: &Queue, id: AtlasImageId) {
+        let entry = match self.entries.remove(&id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let page = &mut self.pages[entry.page];
+        if let Some(row) = page.packer.free(entry.shelf) {
+            let blank = vec![0u8; (row.width * row.height * 4) as usize];
+            Self::write_page_region(queue, &page.texture, row.x, row.y, row.width, row.height, &blank);
+        }
+    }
+
+    // Throws away all shelves and packs the live images again, tallest first. Pages are added when they're needed,
+    // an image that can't be placed on any page is removed and its id returned.
+    pub fn repack(&mut self, device: &Device, queue: &Queue) -> Vec<AtlasImageId> {
+        let mut entries: Vec<(AtlasImageId, AtlasEntry)> = self.entries.drain().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.1.rect.height));
+        for page in &mut self.pages {
+            page.packer.clear();
+        }
+        let blank = vec![0u8; (ATLAS_PAGE_SIZE * ATLAS_PAGE_SIZE * 4) as usize];
+        for page in &self.pages {
+            Self::write_page_region(queue, &page.texture, 0, 0, ATLAS_PAGE_SIZE, ATLAS_PAGE_SIZE, &blank);
+            Self::write_white_block(queue, &page.texture);
+        }
+        let mut dropped = vec![];
+        for (id, mut entry) in entries {
+            let (page, shelf, rect) = match self.allocate(device, queue, entry.rect.width, entry.rect.height) {
+                Some(location) => location,
+                None => {
+                    dropped.push(id);
+                    continue;
+                }
+            };
+            entry.page = page;
+            entry.shelf = shelf;
+            entry.rect = rect;
+            self.upload(queue, &entry);
+            self.entries.insert(id, entry);
+        }
+        // Pages at the end that ended up empty are released.
+        while self.pages.len() > 1 && !self.entries.values().any(|e| e.page == self.pages.len() - 1) {
+            self.pages.pop();
+        }
+        return dropped;
+    }
+
+    pub fn get_region(&self, id: AtlasImageId) -> Option<AtlasRegion> {
+        let entry = self.entries.get(&id)?;
+        let size = ATLAS_PAGE_SIZE as f32;
+        return Some(AtlasRegion {
+            page: entry.page,
+            uv_min: [entry.rect.x as f32 / size, entry.rect.y as f32 / size],
+            uv_max: [(entry.rect.x + entry.rect.width) as f32 / size, (entry.rect.y + entry.rect.height) as f32 / size],
+        });
+    }
+
+    pub fn get_image_size(&self, id: AtlasImageId) -> Option<(u32, u32)> {
+        let entry = self.entries.get(&id)?;
+        return Some((entry.rect.width, entry.rect.height));
+    }
+
+    // The uv to sample for a flat coloured quad, it lands in the middle of the white block.
+    pub fn white_uv(&self) -> [f32; 2] {
+        let uv = (WHITE_BLOCK_SIZE as f32 / 2.0) / ATLAS_PAGE_SIZE as f32;
+        return [uv, uv];
+    }
+
+    pub fn get_bind_group(&self, page: usize) -> &BindGroup {
+        return &self.pages[page].bind_group;
+    }
+
+    pub fn page_count(&self) -> usize {
+        return self.pages.len();
+    }
+
+    // Whether the image and its padding fit below the white block of an empty page.
+    fn fits_on_page(width: u32, height: u32) -> bool {
+        return width <= ATLAS_PAGE_SIZE - ATLAS_PADDING * 2 && height <= ATLAS_PAGE_SIZE - WHITE_BLOCK_SIZE - ATLAS_PADDING * 3;
+    }
+
+    // The image has to fit on a page, insert checks that before anything is allocated.
+    fn allocate(&mut self, device: &Device, queue: &Queue, width: u32, height: u32) -> Option<(usize, usize, AtlasRect)> {
+        let padded_width = width + ATLAS_PADDING * 2;
+        let padded_height = height + ATLAS_PADDING * 2;
+
+        for page_index in 0..self.pages.len() {
+            if let Some((shelf, rect)) = self.pages[page_index].packer.allocate(padded_width, padded_height) {
+                return Some((page_index, shelf, rect));
+            }
+        }
+        self.add_page(device, queue);
+        let page_index = self.pages.len() - 1;
+        let (shelf, rect) = self.pages[page_index].packer.allocate(padded_width, padded_height)?;
+        return Some((page_index, shelf, rect));
+    }
+
+    fn add_page(&mut self, device: &Device, queue: &Queue) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture Atlas Page"),
+            size: wgpu::Extent3d {
+                width: ATLAS_PAGE_SIZE,
+                height: ATLAS_PAGE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb, // Images are sRGB, sampling gives the shaders linear colors
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view: TextureView = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Atlas Page Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        Self::write_white_block(queue, &texture);
+
+        self.pages.push(AtlasPage {
+            texture,
+            bind_group,
+            packer: ShelfPacker::new(),
+        });
+    }
+
+    fn upload(&self, queue: &Queue, entry: &AtlasEntry) {
+        let page = &self.pages[entry.page];
+        let rect = entry.rect;
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let padded = extrude_edges(rect.width, rect.height, &entry.pixels);
+        Self::write_page_region(queue, &page.texture, rect.x - ATLAS_PADDING, rect.y - ATLAS_PADDING, rect.width + ATLAS_PADDING * 2, rect.height + ATLAS_PADDING * 2, &padded);
+    }
+
+    fn write_white_block(queue: &Queue, texture: &Texture) {
+        let white = vec![255u8; (WHITE_BLOCK_SIZE * WHITE_BLOCK_SIZE * 4) as usize];
+        Self::write_page_region(queue, texture, 0, 0, WHITE_BLOCK_SIZE, WHITE_BLOCK_SIZE, &white);
+    }
+
+    fn write_page_region(queue: &Queue, texture: &Texture, x: u32, y: u32, width: u32, height: u32, pixels: &[u8]) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+impl ShelfPacker {
+    fn new() -> ShelfPacker {
+        return ShelfPacker {
+            shelves: vec![],
+            next_shelf_y: WHITE_BLOCK_SIZE + ATLAS_PADDING,
+        }
+    }
+
+    // Returns the shelf and the rect inside the padding, None when the page has no room left.
+    fn allocate(&mut self, padded_width: u32, padded_height: u32) -> Option<(usize, AtlasRect)> {
+        // Prefer the shelf that wastes the least height.
+        let mut best: Option<usize> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height < padded_height || ATLAS_PAGE_SIZE - shelf.cursor_x < padded_width {
+                continue;
+            }
+            if best.is_none_or(|b| self.shelves[b].height > shelf.height) {
+                best = Some(i);
+            }
+        }
+
+        let shelf_index = match best {
+            Some(i) => i,
+            None => {
+                if ATLAS_PAGE_SIZE - self.next_shelf_y < padded_height {
+                    return None;
+                }
+                self.shelves.push(Shelf {
+                    y: self.next_shelf_y,
+                    height: padded_height,
+                    cursor_x: 0,
+                    live: 0,
+                });
+                self.next_shelf_y += padded_height;
+                self.shelves.len() - 1
+            }
+        };
+
+        let shelf = &mut self.shelves[shelf_index];
+        let rect = AtlasRect {
+            x: shelf.cursor_x + ATLAS_PADDING,
+            y: shelf.y + ATLAS_PADDING,
+            width: padded_width - ATLAS_PADDING * 2,
+            height: padded_height - ATLAS_PADDING * 2,
+        };
+        shelf.cursor_x += padded_width;
+        shelf.live += 1;
+        return Some((shelf_index, rect));
+    }
+
+    // Only a shelf nothing lives on anymore is handed out again, as a whole row. Returns that row once it's empty.
+    fn free(&mut self, shelf: usize) -> Option<AtlasRect> {
+        let shelf = &mut self.shelves[shelf];
+        shelf.live -= 1;
+        if shelf.live > 0 {
+            return None;
+        }
+        shelf.cursor_x = 0;
+        return Some(AtlasRect {
+            x: 0,
+            y: shelf.y,
+            width: ATLAS_PAGE_SIZE,
+            height: shelf.height,
+        });
+    }
+
+    fn clear(&mut self) {
+        self.shelves.clear();
+        self.next_shelf_y = WHITE_BLOCK_SIZE + ATLAS_PADDING;
+    }
+}
+
+// Surrounds the RGBA8 image with ATLAS_PADDING texels copied from its nearest edge.
+fn extrude_edges(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let padded_width = width + ATLAS_PADDING * 2;
+    let padded_height = height + ATLAS_PADDING * 2;
+    let mut padded = Vec::with_capacity((padded_width * padded_height * 4) as usize);
+    for y in 0..padded_height {
+        let source_y = y.saturating_sub(ATLAS_PADDING).min(height - 1);
+        for x in 0..padded_width {
+            let source_x = x.saturating_sub(ATLAS_PADDING).min(width - 1);
+            let texel = ((source_y * width + source_x) * 4) as usize;
+            padded.extend_from_slice(&pixels[texel..texel + 4]);
+        }
+    }
+    return padded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn padded(size: u32) -> u32 {
+        return size + ATLAS_PADDING * 2;
+    }
+
+    #[test]
+    fn images_of_the_same_height_share_a_shelf() {
+        let mut packer = ShelfPacker::new();
+        let (first_shelf, first) = packer.allocate(padded(16), padded(16)).unwrap();
+        let (second_shelf, second) = packer.allocate(padded(16), padded(16)).unwrap();
+        assert_eq!(first_shelf, second_shelf);
+        assert_eq!(first.y, second.y);
+        assert_eq!(second.x, first.x + padded(16));
+        // Nothing overlaps the white block.
+        assert!(first.y >= WHITE_BLOCK_SIZE + ATLAS_PADDING);
+    }
+
+    #[test]
+    fn a_freed_shelf_is_reused() {
+        let mut packer = ShelfPacker::new();
+        let (first_shelf, first) = packer.allocate(padded(32), padded(32)).unwrap();
+        let (second_shelf, _) = packer.allocate(padded(64), padded(64)).unwrap();
+        assert_ne!(first_shelf, second_shelf);
+        packer.free(first_shelf);
+        let (shelf, rect) = packer.allocate(padded(30), padded(30)).unwrap();
+        assert_eq!(shelf, first_shelf);
+        assert_eq!((rect.x, rect.y), (first.x, first.y));
+        assert_eq!(packer.shelves.len(), 2);
+    }
+
+    #[test]
+    fn only_an_emptied_shelf_is_cleared() {
+        let mut packer = ShelfPacker::new();
+        let (shelf, first) = packer.allocate(padded(16), padded(16)).unwrap();
+        packer.allocate(padded(16), padded(16)).unwrap();
+        assert!(packer.free(shelf).is_none());
+        let row = packer.free(shelf).unwrap();
+        assert_eq!((row.x, row.y, row.width, row.height), (0, first.y - ATLAS_PADDING, ATLAS_PAGE_SIZE, padded(16)));
+    }
+
+    #[test]
+    fn oversized_images_are_rejected_before_anything_is_allocated() {
+        assert!(TextureAtlas::fits_on_page(ATLAS_PAGE_SIZE - ATLAS_PADDING * 2, 1));
+        assert!(!TextureAtlas::fits_on_page(ATLAS_PAGE_SIZE - ATLAS_PADDING * 2 + 1, 1));
+        assert!(!TextureAtlas::fits_on_page(1, ATLAS_PAGE_SIZE - WHITE_BLOCK_SIZE - ATLAS_PADDING * 3 + 1));
+        assert!(!TextureAtlas::fits_on_page(u32::MAX, u32::MAX));
+        // The largest image that fits gets its shelf on an empty page.
+        let mut packer = ShelfPacker::new();
+        let height = ATLAS_PAGE_SIZE - WHITE_BLOCK_SIZE - ATLAS_PADDING * 3;
+        assert!(packer.allocate(padded(ATLAS_PAGE_SIZE - ATLAS_PADDING * 2), padded(height)).is_some());
+    }
+
+    #[test]
+    fn the_padding_repeats_the_edge_texels() {
+        // A 2x1 image, a red texel and a blue one.
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+        let extruded = extrude_edges(2, 1, &pixels);
+        assert_eq!(extruded.len(), (padded(2) * padded(1) * 4) as usize);
+        let texel = |x: u32, y: u32| -> [u8; 4] {
+            let i = ((y * padded(2) + x) * 4) as usize;
+            return [extruded[i], extruded[i + 1], extruded[i + 2], extruded[i + 3]];
+        };
+        for y in 0..padded(1) {
+            assert_eq!(texel(0, y), [255, 0, 0, 255]);
+            assert_eq!(texel(1, y), [255, 0, 0, 255]);
+            assert_eq!(texel(2, y), [0, 0, 255, 255]);
+            assert_eq!(texel(3, y), [0, 0, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn a_shelf_in_use_keeps_its_gaps() {
+        let mut packer = ShelfPacker::new();
+        let (shelf, _) = packer.allocate(padded(16), padded(16)).unwrap();
+        let (_, second) = packer.allocate(padded(16), padded(16)).unwrap();
+        packer.free(shelf);
+        let (_, third) = packer.allocate(padded(16), padded(16)).unwrap();
+        assert_eq!(third.x, second.x + padded(16));
+    }
+
+    #[test]
+    fn a_full_page_only_takes_what_fits_below_its_last_shelf() {
+        let mut packer = ShelfPacker::new();
+        let row = padded(ATLAS_PAGE_SIZE - ATLAS_PADDING * 2);
+        let mut rows = 0;
+        while packer.allocate(row, 100).is_some() {
+            rows += 1;
+        }
+        assert_eq!(rows, (ATLAS_PAGE_SIZE - WHITE_BLOCK_SIZE - ATLAS_PADDING) / 100);
+        let gap = (ATLAS_PAGE_SIZE - WHITE_BLOCK_SIZE - ATLAS_PADDING) % 100;
+        assert!(packer.allocate(padded(1), gap + 1).is_none());
+        assert!(packer.allocate(padded(1), gap).is_some());
+        assert!(packer.allocate(row, 1).is_none());
+        packer.clear();
+        let (shelf, rect) = packer.allocate(row, 100).unwrap();
+        assert_eq!(shelf, 0);
+        assert_eq!(rect.y, WHITE_BLOCK_SIZE + ATLAS_PADDING * 2);
+    }
+}
@@ -1,29 +1,90 @@
-//Used for rendering rectangles
-//Not done
+//Used for rendering rectangles and images from the texture atlas
+//Flat coloured quads sample the white block every atlas page has
+//Pipeline2D builds variants of it with these defines:
+//  ROUNDED_CORNERS  cuts the corners of quads off by the vertex radius
 
 struct UIVertexInput {
     @location(0) uv: vec2<f32>, 
     @location(1) color: vec4<f32>,
     @location(2) pos: vec3<f32>,
+    @location(3) edge: vec4<f32>,
+    @location(4) radius: f32,
 };
 
 struct UIVertexOutput {
     @location(0) uv: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) edge: vec4<f32>,
+    @location(3) radius: f32,
     @builtin(position) position: vec4<f32>,
 };
 
+//Pixels to clip space, with the Pipeline2D camera applied for world space batches
+@group(1)
+@binding(0)
+var<uniform> view_proj: mat4x4<f32>;
+
 @vertex
 fn vertex(in: UIVertexInput) -> UIVertexOutput {
     var out: UIVertexOutput;
     out.uv = in.uv;
     out.color = in.color;
+    out.edge = in.edge;
+    out.radius = in.radius;
     //wgpu shaders use -1 to 1
-    out.position = vec4(in.pos.x, in.pos.y, in.pos.z, 1.0);
+    out.position = view_proj * vec4(in.pos.x, in.pos.y, in.pos.z, 1.0);
     return out;
 }
 
+@group(0)
+@binding(0)
+var atlas_texture: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var atlas_sampler: sampler;
+
+//Analytic anti-aliasing for when MSAA is off
+//edge.xy is the position inside the quad and edge.zw its size, both in pixels
+fn edge_coverage(edge: vec4<f32>) -> f32 {
+    //Derivatives have to be taken before branching
+    let pixel = max(length(fwidth(edge.xy)) * 0.7071, 0.0001);
+    let distance = min(min(edge.x, edge.z - edge.x), min(edge.y, edge.w - edge.y));
+    let coverage = clamp(distance / pixel + 0.5, 0.0, 1.0);
+    return select(coverage, 1.0, edge.z < 0.0);
+}
+
+#ifdef ROUNDED_CORNERS
+#include "sdf.wgsl"
+
+//Rounded quads always get their edge, with or without MSAA
+fn rounded_coverage(edge: vec4<f32>, radius: f32) -> f32 {
+    let pixel = max(length(fwidth(edge.xy)) * 0.7071, 0.0001);
+    let half_size = edge.zw * 0.5;
+    let distance = rounded_rect_distance(edge.xy - half_size, half_size, radius);
+    return clamp(0.5 - distance / pixel, 0.0, 1.0);
+}
+#endif
+
+//How much of the pixel the quad covers
+fn quad_coverage(vertex: UIVertexOutput) -> f32 {
+#ifdef ROUNDED_CORNERS
+    return rounded_coverage(vertex.edge, vertex.radius);
+#else
+    return edge_coverage(vertex.edge);
+#endif
+}
+
 @fragment
 fn fragment(vertex: UIVertexOutput) -> @location(0) vec4<f32> {
-    return vertex.color;
+    let color = textureSample(atlas_texture, atlas_sampler, vertex.uv) * vertex.color;
+    let alpha = color.a * quad_coverage(vertex);
+    //Blending expects premultiplied alpha
+    return vec4(color.rgb * alpha, alpha);
+}
+
+//Composites a layer texture, its contents are already premultiplied and color holds the opacity in every channel
+@fragment
+fn fragment_layer(vertex: UIVertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(atlas_texture, atlas_sampler, vertex.uv) * vertex.color;
 }
\ No newline at end of file